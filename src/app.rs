@@ -1,10 +1,10 @@
 #![allow(dead_code)]
 #![allow(clippy::no_effect)]
+use anyhow::Context as _;
 use eframe::egui;
 use eframe::egui_wgpu;
 use eframe::egui_wgpu::RenderState;
 use eframe::epaint::PaintCallbackInfo;
-use eframe::wgpu::util::DeviceExt;
 use egui::panel::Side;
 use egui::Id;
 use egui_wgpu::wgpu;
@@ -14,21 +14,136 @@ use instant::Instant;
 use log::{error, info};
 #[cfg(not(target_arch = "wasm32"))]
 use notify::Watcher;
-use std::borrow::Cow;
+use std::time::Duration;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod animation;
+mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+mod check;
+mod compute;
+mod diagnostics;
+#[cfg(not(target_arch = "wasm32"))]
+mod float_export;
+mod frag_coord;
+mod frame_clock;
+#[cfg(not(target_arch = "wasm32"))]
+mod gallery;
+#[cfg(not(target_arch = "wasm32"))]
+mod golden;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+mod ir;
+mod portability;
+mod renderer;
 mod shader;
+#[cfg(not(target_arch = "wasm32"))]
+mod stream;
+mod template;
+mod validate;
+mod vertex_art;
+#[cfg(not(target_arch = "wasm32"))]
+pub use animation::*;
+pub use cache::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use check::*;
+pub use compute::*;
+pub use diagnostics::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use float_export::*;
+pub use frag_coord::*;
+pub use frame_clock::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use gallery::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use golden::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use headless::*;
+pub use ir::*;
+pub use portability::*;
+pub use renderer::*;
 pub use shader::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use stream::*;
+pub use template::*;
+pub use validate::*;
+pub use vertex_art::*;
 
 pub type Result<T> = anyhow::Result<T>;
 
+/// Default idle time after the last keystroke before the shader is recompiled.
+pub const DEFAULT_COMPILE_DELAY: Duration = Duration::from_millis(300);
+
+/// Storage key for the persisted [`CompileSettings`] used without an external
+/// shader file.
+const COMPILE_SETTINGS_KEY: &str = "compile_settings";
+
+/// Storage key for the [`CompileSettings`] of each external shader file.
+#[cfg(not(target_arch = "wasm32"))]
+const PROJECT_COMPILE_SETTINGS_KEY: &str = "project_compile_settings";
+
+/// Storage key for whether the WebGL2 portability check is on.
+const WEBGL2_PORTABILITY_KEY: &str = "webgl2_portability";
+
+/// Storage key for the persisted [`RenderMode`].
+const RENDER_MODE_KEY: &str = "render_mode";
+
+/// Storage key for the persisted [`VertexArt`] settings.
+const VERTEX_ART_KEY: &str = "vertex_art";
+
+/// Storage key for the persisted [`FragCoordConvention`].
+const FRAG_COORD_CONVENTION_KEY: &str = "frag_coord_convention";
+
+/// Storage key for the persisted [`OutputSettings`].
+const OUTPUT_SETTINGS_KEY: &str = "output_settings";
+
+/// Outcome of the last fragment shader compile, shown so compiler options can
+/// be compared.
+struct CompileStats {
+    /// Size of the module handed to wgpu.
+    size: usize,
+    compile_time: Duration,
+    cached: bool,
+}
+
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 pub struct App {
     wgpu_callback: WgpuCallback,
     render_state: RenderState,
     shader_dirty: bool,
+    /// Time of the last edit in the shader editor; the recompile waits until
+    /// the editor has been idle for `compile_delay`.
+    shader_edited_at: Option<Instant>,
+    compile_delay: Duration,
     show_logger: bool,
+    show_ir: bool,
+    /// Set when the IR viewer needs rebuilding from the current shader.
+    ir_dirty: bool,
+    shader_ir: Option<ShaderIr>,
     shader_editor: bool,
     shader_content: String,
+    shader_language: ShaderLanguage,
+    render_mode: RenderMode,
+    vertex_art: VertexArt,
+    compute: ComputeSettings,
+    /// Set to reallocate the compute storage, clearing it, on the next compile.
+    compute_reset: bool,
+    /// Compute pass shown in the editor instead of the image shader.
+    editing_compute_pass: Option<usize>,
+    /// Mode the current pipeline was built for; it keeps rendering in that
+    /// mode until a pipeline for the new one compiles.
+    pipeline_mode: RenderMode,
+    compile_settings: CompileSettings,
+    /// Also compile GLSL under ES rules and report what would break on
+    /// Shadertoy's WebGL2. It does not change the SPIR-V, so it is not part of
+    /// [`CompileSettings`].
+    webgl2_portability: bool,
+    frag_coord_convention: FragCoordConvention,
+    output_settings: OutputSettings,
+    shader_cache: ShaderCache,
+    /// Warnings and errors from the most recent compile.
+    diagnostics: Vec<Diagnostic>,
+    compile_stats: Option<CompileStats>,
     start_time: Instant,
     #[cfg(not(target_arch = "wasm32"))]
     _vertex_shader_file_watcher: notify::RecommendedWatcher,
@@ -44,71 +159,19 @@ pub struct App {
     external_glsl_file_watch_rx: Option<std::sync::mpsc::Receiver<notify::Result<notify::Event>>>,
     #[cfg(not(target_arch = "wasm32"))]
     external_glsl_file_path: Option<String>,
+    /// Compile settings of each external shader file, by path.
+    #[cfg(not(target_arch = "wasm32"))]
+    project_compile_settings: std::collections::HashMap<String, CompileSettings>,
+    /// Compile settings used while no external shader file is open.
+    #[cfg(not(target_arch = "wasm32"))]
+    default_compile_settings: CompileSettings,
     #[cfg(not(target_arch = "wasm32"))]
     monitor_external_file: bool,
+    /// Image the float export button writes.
+    #[cfg(not(target_arch = "wasm32"))]
+    float_export_pass: ExportPass,
 }
 
-fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("bind_group_layout"),
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        }],
-    })
-}
-
-fn create_pipeline(
-    device: &wgpu::Device,
-    vertex_spirv: Cow<'_, [u32]>,
-    fragment_spirv: Cow<'_, [u32]>,
-    target_format: wgpu::TextureFormat,
-) -> wgpu::RenderPipeline {
-    let bind_group_layout = create_bind_group_layout(device);
-    let vertex_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("vertex_shader"),
-        source: wgpu::ShaderSource::SpirV(vertex_spirv),
-    });
-    let fragment_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("fragment_shader"),
-        // convert u8 to u32
-        source: wgpu::ShaderSource::SpirV(fragment_spirv),
-    });
-
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("pipeline_layout"),
-        bind_group_layouts: &[&bind_group_layout],
-        push_constant_ranges: &[],
-    });
-
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("render_pipeline"),
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &vertex_shader,
-            entry_point: Some("main"),
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &fragment_shader,
-            entry_point: Some("main"),
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-            targets: &[Some(target_format.into())],
-        }),
-        multiview: None,
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        cache: None,
-    })
-}
 impl App {
     /// Called once before the first frame.
     #[must_use]
@@ -116,35 +179,49 @@ impl App {
         // Initialize the logging system, but ignore possible errors
         let _ = egui_logger::builder().init();
         let render_state = cc.wgpu_render_state.as_ref().expect("WGPU enabled");
+        let compile_settings: CompileSettings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, COMPILE_SETTINGS_KEY))
+            .unwrap_or_default();
+        let webgl2_portability = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, WEBGL2_PORTABILITY_KEY))
+            .unwrap_or_default();
+        let render_mode: RenderMode = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, RENDER_MODE_KEY))
+            .unwrap_or_default();
+        let vertex_art: VertexArt = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, VERTEX_ART_KEY))
+            .unwrap_or_default();
+        let frag_coord_convention: FragCoordConvention = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, FRAG_COORD_CONVENTION_KEY))
+            .unwrap_or_default();
 
-        let device = &render_state.device;
+        let output_settings: OutputSettings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, OUTPUT_SETTINGS_KEY))
+            .unwrap_or_default();
 
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: &[0u8; std::mem::size_of::<WgpuUniform>()],
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
-        });
-        let bind_group_layout = create_bind_group_layout(device);
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-        });
         render_state
             .renderer
             .write()
             .callback_resources
-            .insert(TriangleRenderResources {
-                pipeline: None,
-                bind_group,
-                uniform_buffer,
-            });
+            .insert(Renderer::new(
+                &render_state.device,
+                render_state.target_format,
+            ));
 
         #[cfg(not(target_arch = "wasm32"))]
         {
+            // The templates below are watched, so edits to them are picked up.
+            reload_builtin_templates(true);
+            let project_compile_settings = cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, PROJECT_COMPILE_SETTINGS_KEY))
+                .unwrap_or_default();
             let vertex_shader_file_watcher;
             let vertex_shader_file_watch_rx;
             let fragment_shader_file_watcher;
@@ -204,9 +281,28 @@ impl App {
                 wgpu_callback: WgpuCallback::default(),
                 render_state: render_state.clone(),
                 shader_dirty: true,
+                shader_edited_at: None,
+                compile_delay: DEFAULT_COMPILE_DELAY,
                 show_logger: true,
+                show_ir: false,
+                ir_dirty: false,
+                shader_ir: None,
                 shader_editor: true,
                 shader_content: include_str!("app/default.glsl").to_string(),
+                shader_language: ShaderLanguage::Glsl,
+                render_mode,
+                vertex_art,
+                compute: ComputeSettings::default(),
+                compute_reset: false,
+                editing_compute_pass: None,
+                pipeline_mode: RenderMode::Fragment,
+                compile_settings: compile_settings.clone(),
+                webgl2_portability,
+                frag_coord_convention,
+                output_settings,
+                diagnostics: Vec::new(),
+                compile_stats: None,
+                shader_cache: ShaderCache::default(),
                 start_time: Instant::now(),
                 _vertex_shader_file_watcher: vertex_shader_file_watcher,
                 vertex_shader_file_watch_rx,
@@ -215,7 +311,10 @@ impl App {
                 _external_glsl_file_watcher: None,
                 external_glsl_file_watch_rx: None,
                 external_glsl_file_path: None,
+                project_compile_settings,
+                default_compile_settings: compile_settings,
                 monitor_external_file: false,
+                float_export_pass: ExportPass::default(),
             }
         }
         #[cfg(target_arch = "wasm32")]
@@ -224,14 +323,64 @@ impl App {
                 wgpu_callback: WgpuCallback::default(),
                 render_state: render_state.clone(),
                 shader_dirty: true,
+                shader_edited_at: None,
+                compile_delay: DEFAULT_COMPILE_DELAY,
                 show_logger: true,
+                show_ir: false,
+                ir_dirty: false,
+                shader_ir: None,
                 shader_editor: false,
+                shader_cache: ShaderCache::default(),
                 start_time: Instant::now(),
                 shader_content: include_str!("app/default.glsl").to_string(),
+                shader_language: ShaderLanguage::Glsl,
+                render_mode,
+                vertex_art,
+                compute: ComputeSettings::default(),
+                compute_reset: false,
+                editing_compute_pass: None,
+                pipeline_mode: RenderMode::Fragment,
+                compile_settings,
+                webgl2_portability,
+                frag_coord_convention,
+                output_settings,
+                diagnostics: Vec::new(),
+                compile_stats: None,
             }
         }
     }
 
+    /// Reads back the chosen pass of the last frame and writes it to `path`
+    /// as EXR or HDR.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_float_image(&self, path: &std::path::Path) -> Result<()> {
+        let egui_renderer = self.render_state.renderer.read();
+        let renderer: &Renderer = egui_renderer
+            .callback_resources
+            .get()
+            .context("The renderer is missing")?;
+        let texture = match self.float_export_pass {
+            ExportPass::ComputeImage => {
+                &renderer
+                    .compute
+                    .as_ref()
+                    .context("The shader has no compute passes")?
+                    .image
+            }
+            ExportPass::Image | ExportPass::Output => {
+                &renderer
+                    .target()
+                    .context("Nothing has been rendered yet")?
+                    .texture
+            }
+        };
+        let device = &self.render_state.device;
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("float_export_encoder"),
+        });
+        read_float_texture(device, &self.render_state.queue, encoder, texture)?.write_float(path)
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     fn set_external_glsl_file_watcher(&mut self, file_path: &str) -> Result<()> {
         use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
@@ -254,21 +403,6 @@ impl App {
             ));
         }
 
-        // Try to read the file content (test permissions in advance)
-        match std::fs::read_to_string(file_path) {
-            Ok(content) => {
-                info!(
-                    "Successfully read file content, length: {} bytes",
-                    content.len()
-                );
-                self.shader_content = content;
-                self.shader_dirty = true;
-            }
-            Err(e) => {
-                return Err(anyhow::anyhow!("Cannot read file: {}", e));
-            }
-        }
-
         // Normalize file path
         let canonical_path = match std::fs::canonicalize(file_path) {
             Ok(path) => path,
@@ -300,14 +434,38 @@ impl App {
             }
         }
 
-        // Save file path and watcher
+        // Save file path and watcher, and switch to the file's compile settings
+        self.remember_compile_settings();
+        self.compile_settings = CompileSettings {
+            compute: self.compile_settings.compute,
+            ..self
+                .project_compile_settings
+                .get(&path_str)
+                .unwrap_or(&self.default_compile_settings)
+                .clone()
+        };
+        self.shader_dirty = true;
         self.external_glsl_file_path = Some(path_str);
         self._external_glsl_file_watcher = Some(watcher);
         self.external_glsl_file_watch_rx = Some(rx);
         self.monitor_external_file = true;
 
         info!("Successfully set up external GLSL file monitoring");
-        Ok(())
+        // Load it into whichever shader the current mode edits, as reloads do.
+        self.read_external_glsl_file()
+    }
+
+    /// Stores the current compile settings as those of the open external file,
+    /// or as the defaults when there is none.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn remember_compile_settings(&mut self) {
+        match &self.external_glsl_file_path {
+            Some(path) => {
+                self.project_compile_settings
+                    .insert(path.clone(), self.compile_settings.clone());
+            }
+            None => self.default_compile_settings = self.compile_settings.clone(),
+        }
     }
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -335,7 +493,16 @@ impl App {
                         "Successfully read file content, length: {} bytes",
                         content.len()
                     );
-                    self.shader_content = content;
+                    // The file holds whichever shader the current mode edits.
+                    match self.render_mode {
+                        RenderMode::Fragment => {
+                            self.shader_content = content;
+                            if let Some(language) = ShaderLanguage::from_path(file_path) {
+                                self.shader_language = language;
+                            }
+                        }
+                        RenderMode::VertexArt => self.vertex_art.content = content,
+                    }
                     self.shader_dirty = true;
                     info!("Shader content updated and marked for recompilation");
                 }
@@ -350,6 +517,72 @@ impl App {
         Ok(())
     }
 
+    /// Compiles the current fragment shader again (normally a cache hit) and
+    /// translates it for the IR viewer.
+    fn build_shader_ir(&mut self) -> Result<ShaderIr> {
+        if self.render_mode == RenderMode::VertexArt {
+            return self.build_vertex_art_ir();
+        }
+        let shader = load_fragment_shader(
+            &self.shader_content,
+            self.shader_language,
+            &self.compile_settings,
+            &mut self.shader_cache,
+            &mut Vec::new(),
+        )?;
+        let language = self.shader_language;
+        let spirv_assembly = match language {
+            #[cfg(not(target_arch = "wasm32"))]
+            ShaderLanguage::Glsl | ShaderLanguage::Hlsl => {
+                fragment_shader_source(&self.shader_content, language, &self.compile_settings)
+                    .and_then(|source| {
+                        disassemble_shader(
+                            &source,
+                            shaderc::ShaderKind::Fragment,
+                            language,
+                            &self.compile_settings,
+                        )
+                    })
+            }
+            #[cfg(target_arch = "wasm32")]
+            ShaderLanguage::Glsl | ShaderLanguage::Hlsl => {
+                Err(anyhow::anyhow!("shaderc is not available on the web"))
+            }
+            ShaderLanguage::Wgsl => Err(anyhow::anyhow!(
+                "WGSL is handed to wgpu directly and never compiled to SPIR-V"
+            )),
+        };
+        Ok(ShaderIr::new(&shader, spirv_assembly))
+    }
+
+    /// IR of the user's vertex shader in the vertex art mode.
+    fn build_vertex_art_ir(&mut self) -> Result<ShaderIr> {
+        let (vertex, _) = load_vertex_art_shaders(
+            &self.vertex_art.content,
+            &self.compile_settings,
+            &mut self.shader_cache,
+            &mut Vec::new(),
+        )?;
+        #[cfg(not(target_arch = "wasm32"))]
+        let spirv_assembly =
+            vertex_art_shader_source(&self.vertex_art.content, &self.compile_settings).and_then(
+                |source| {
+                    disassemble_shader(
+                        &source,
+                        shaderc::ShaderKind::Vertex,
+                        ShaderLanguage::Glsl,
+                        &self.compile_settings,
+                    )
+                },
+            );
+        #[cfg(target_arch = "wasm32")]
+        let spirv_assembly = Err(anyhow::anyhow!("shaderc is not available on the web"));
+        Ok(ShaderIr::new(
+            &CompiledShader::SpirV(vertex),
+            spirv_assembly,
+        ))
+    }
+
     #[cfg(target_arch = "wasm32")]
     fn handle_web_specific_tasks(&mut self, _ctx: &egui::Context) {
         // This method is intentionally left empty for now
@@ -358,58 +591,33 @@ impl App {
     }
 }
 
-struct TriangleRenderResources {
-    pipeline: Option<wgpu::RenderPipeline>,
-    bind_group: wgpu::BindGroup,
-    uniform_buffer: wgpu::Buffer,
-}
 #[derive(Default, Clone)]
 struct WgpuCallback {
     uniform: WgpuUniform,
+    /// Vertices drawn with the current pipeline.
+    vertex_count: u32,
+    output: OutputSettings,
 }
-#[derive(Clone)]
-#[std140::repr_std140]
-struct WgpuUniform {
-    resolution: std140::vec2,
-    time: std140::float,
-    time_delta: std140::float,
-    frame: std140::float,
-    channel_time: std140::vec4,
-    mouse: std140::vec4,
-    date: std140::vec4,
-    sample_rate: std140::float,
-}
-impl Default for WgpuUniform {
-    fn default() -> Self {
-        Self {
-            resolution: std140::vec2::zero(),
-            time: std140::float(0.0),
-            time_delta: std140::float(0.0),
-            frame: std140::float(0.0),
-            channel_time: std140::vec4::zero(),
-            mouse: std140::vec4::zero(),
-            date: std140::vec4::zero(),
-            sample_rate: std140::float(0.0),
-        }
-    }
-}
-
 impl egui_wgpu::CallbackTrait for WgpuCallback {
     fn prepare(
         &self,
-        _device: &Device,
+        device: &Device,
         queue: &Queue,
         _screen_descriptor: &ScreenDescriptor,
-        _egui_encoder: &mut CommandEncoder,
+        egui_encoder: &mut CommandEncoder,
         callback_resources: &mut CallbackResources,
     ) -> Vec<CommandBuffer> {
-        let resources: &TriangleRenderResources = callback_resources.get().unwrap();
-        queue.write_buffer(&resources.uniform_buffer, 0, unsafe {
-            std::slice::from_raw_parts(
-                std::ptr::from_ref::<WgpuUniform>(&self.uniform).cast::<u8>(),
-                std::mem::size_of::<WgpuUniform>(),
-            )
-        });
+        let renderer: &mut Renderer = callback_resources.get_mut().unwrap();
+        // Recorded before egui's render pass, which then only composites the
+        // result.
+        renderer.prepare(
+            device,
+            queue,
+            egui_encoder,
+            &self.uniform,
+            self.vertex_count,
+            &self.output,
+        );
         Vec::new()
     }
 
@@ -429,12 +637,8 @@ impl egui_wgpu::CallbackTrait for WgpuCallback {
         render_pass: &mut RenderPass<'static>,
         callback_resources: &CallbackResources,
     ) {
-        let resources: &TriangleRenderResources = callback_resources.get().unwrap();
-        if let Some(pipeline) = &resources.pipeline {
-            render_pass.set_pipeline(pipeline);
-            render_pass.set_bind_group(0, &resources.bind_group, &[]);
-            render_pass.draw(0..6, 0..1);
-        }
+        let renderer: &Renderer = callback_resources.get().unwrap();
+        renderer.paint(render_pass);
     }
 }
 
@@ -447,6 +651,13 @@ impl eframe::App for App {
         #[cfg(target_arch = "wasm32")]
         self.handle_web_specific_tasks(ctx);
 
+        // Pipeline errors WebGPU only reported after the pipeline was created.
+        for message in take_late_errors() {
+            let diagnostic = Diagnostic::error(message);
+            diagnostic.log();
+            self.diagnostics.push(diagnostic);
+        }
+
         // Check for changes to the external GLSL file
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -490,10 +701,10 @@ impl eframe::App for App {
         }
 
         {
-            let mut renderer = self.render_state.renderer.write();
-            let triangle_render_resources = renderer
+            let mut egui_renderer = self.render_state.renderer.write();
+            let renderer = egui_renderer
                 .callback_resources
-                .get_mut::<TriangleRenderResources>()
+                .get_mut::<Renderer>()
                 .unwrap();
             #[cfg(not(target_arch = "wasm32"))]
             {
@@ -517,29 +728,163 @@ impl eframe::App for App {
                     while let Ok(Ok(_)) = self.fragment_shader_file_watch_rx.try_recv() {}
                 }
             }
-            if self.shader_dirty {
-                match (
-                    load_vertex_shader(),
-                    load_fragment_shader(&self.shader_content),
-                ) {
-                    (Ok(vertex_spirv), Ok(fragment_spirv)) => {
-                        triangle_render_resources.pipeline = Some(create_pipeline(
-                            &self.render_state.device,
-                            vertex_spirv,
-                            fragment_spirv,
-                            self.render_state.target_format,
-                        ));
-                        info!("Shader reloaded successfully");
+            // Edits made within `compile_delay` of each other supersede the
+            // pending compile, so only the latest source is compiled.
+            let compile_due = self
+                .shader_edited_at
+                .map_or(true, |edited_at| edited_at.elapsed() >= self.compile_delay);
+            if self.shader_dirty && compile_due {
+                let mut diagnostics = Vec::new();
+                self.compile_settings.compute = self.compute.is_active();
+                let cache_hits = self.shader_cache.hits();
+                let compile_start = Instant::now();
+                let shaders = match self.render_mode {
+                    RenderMode::Fragment => {
+                        let vertex_shader = load_vertex_shader(
+                            &self.compile_settings,
+                            &mut self.shader_cache,
+                            &mut diagnostics,
+                        )
+                        .context("Error loading vertex shader");
+                        let fragment_shader = load_fragment_shader(
+                            &self.shader_content,
+                            self.shader_language,
+                            &self.compile_settings,
+                            &mut self.shader_cache,
+                            &mut diagnostics,
+                        )
+                        .context("Error loading fragment shader");
+                        vertex_shader.and_then(|vertex_shader| {
+                            fragment_shader.map(|fragment_shader| (vertex_shader, fragment_shader))
+                        })
                     }
-                    (Err(vertex_error), _) => {
-                        error!("Error loading vertex shader: {}", vertex_error);
-                    }
-                    (_, Err(fragment_error)) => {
-                        error!("Error loading fragment shader: {}", fragment_error,);
+                    RenderMode::VertexArt => load_vertex_art_shaders(
+                        &self.vertex_art.content,
+                        &self.compile_settings,
+                        &mut self.shader_cache,
+                        &mut diagnostics,
+                    )
+                    .context("Error loading vertex art shader"),
+                };
+                self.compile_stats =
+                    shaders
+                        .as_ref()
+                        .ok()
+                        .map(|(_, fragment_shader)| CompileStats {
+                            size: fragment_shader.size(),
+                            compile_time: compile_start.elapsed(),
+                            cached: self.shader_cache.hits() > cache_hits,
+                        });
+                if self.webgl2_portability
+                    && self.render_mode == RenderMode::Fragment
+                    && self.shader_language == ShaderLanguage::Glsl
+                {
+                    diagnostics.extend(check_es_portability(
+                        &self.shader_content,
+                        &self.compile_settings,
+                        &mut self.shader_cache,
+                    ));
+                }
+                let compute_shaders = self
+                    .compute
+                    .passes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pass)| pass.enabled)
+                    .map(|(index, pass)| {
+                        load_compute_shader(
+                            &pass.content,
+                            &self.compile_settings,
+                            &mut self.shader_cache,
+                            &mut diagnostics,
+                        )
+                        .with_context(|| format!("Error loading compute pass {}", index + 1))
+                        .map(|spirv| (spirv, pass.workgroups))
+                    })
+                    .collect::<Result<Vec<_>>>();
+                let shaders = shaders.and_then(|shaders| Ok((shaders, compute_shaders?)));
+                let topology = match self.render_mode {
+                    RenderMode::Fragment => wgpu::PrimitiveTopology::TriangleList,
+                    RenderMode::VertexArt => self.vertex_art.topology.to_wgpu(),
+                };
+                match shaders {
+                    Ok(((vertex_spirv, fragment_shader), compute_shaders)) => {
+                        let device = &self.render_state.device;
+                        let compute_pipelines = compute_shaders
+                            .into_iter()
+                            .map(|(spirv, workgroups)| {
+                                create_compute_pipeline(device, spirv)
+                                    .map(|pipeline| (pipeline, workgroups))
+                            })
+                            .collect::<Result<Vec<_>>>();
+                        // On failure the previous pipeline keeps rendering.
+                        match compute_pipelines.and_then(|compute_pipelines| {
+                            let pipeline = create_pipeline(
+                                device,
+                                vertex_spirv,
+                                &fragment_shader,
+                                self.output_settings.format.to_wgpu(),
+                                topology,
+                                self.compile_settings
+                                    .compute
+                                    .then(|| self.render_mode.compute_stages()),
+                            )?;
+                            Ok((pipeline, compute_pipelines))
+                        }) {
+                            Ok((pipeline, compute_pipelines)) => {
+                                if !self.compile_settings.compute {
+                                    renderer.compute = None;
+                                } else if self.compute_reset
+                                    || !renderer.compute.as_ref().is_some_and(|compute| {
+                                        compute
+                                            .fits(&self.compute, self.render_mode.compute_stages())
+                                    })
+                                {
+                                    match ComputeResources::new(
+                                        device,
+                                        renderer.uniform_buffer(),
+                                        &self.compute,
+                                        self.render_mode.compute_stages(),
+                                    ) {
+                                        Ok(compute) => renderer.compute = Some(compute),
+                                        Err(err) => {
+                                            diagnostics.extend(Diagnostic::from_error(&err))
+                                        }
+                                    }
+                                }
+                                self.compute_reset = false;
+                                renderer.compute_pipelines = compute_pipelines;
+                                renderer.set_pipeline(
+                                    pipeline,
+                                    self.output_settings.format.to_wgpu(),
+                                    self.compile_settings.compute,
+                                );
+                                self.pipeline_mode = self.render_mode;
+                                self.ir_dirty = true;
+                                info!("Shader reloaded successfully");
+                            }
+                            Err(err) => diagnostics.push(Diagnostic::error(format!(
+                                "Error creating pipeline: {}",
+                                err
+                            ))),
+                        }
                     }
+                    Err(err) => diagnostics.extend(Diagnostic::from_error(&err)),
                 }
+                for diagnostic in &diagnostics {
+                    diagnostic.log();
+                }
+                self.diagnostics = diagnostics;
                 self.shader_dirty = false;
+                self.shader_edited_at = None;
+            }
+        }
+        if self.show_ir && self.ir_dirty {
+            match self.build_shader_ir() {
+                Ok(shader_ir) => self.shader_ir = Some(shader_ir),
+                Err(err) => error!("Failed to build shader IR: {}", err),
             }
+            self.ir_dirty = false;
         }
         // Put your widgets into a `SidePanel`, `TopBottomPanel`, `CentralPanel`, `Window` or `Area`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
@@ -567,12 +912,368 @@ impl eframe::App for App {
                 if ui.button("🔄").clicked() {
                     self.wgpu_callback.uniform.frame = std140::float(0.0);
                     self.start_time = Instant::now();
+                    if self.compute.is_active() {
+                        self.compute_reset = true;
+                        self.shader_dirty = true;
+                    }
+                }
+                let mut delay_ms = self.compile_delay.as_millis() as u64;
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut delay_ms)
+                            .range(0..=5000)
+                            .speed(10)
+                            .prefix("Compile delay: ")
+                            .suffix(" ms"),
+                    )
+                    .changed()
+                {
+                    self.compile_delay = Duration::from_millis(delay_ms);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Shader cache: {} entries, {} KiB",
+                    self.shader_cache.len(),
+                    self.shader_cache.memory_size() / 1024
+                ));
+                if ui.button("Clear cache").clicked() {
+                    match self.shader_cache.clear() {
+                        Ok(_) => info!("Shader cache cleared"),
+                        Err(err) => error!("Failed to clear shader cache: {}", err),
+                    }
                 }
             });
             ui.horizontal(|ui| {
+                let previous_mode = self.render_mode;
+                egui::ComboBox::from_label("Mode")
+                    .selected_text(self.render_mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in RenderMode::ALL {
+                            ui.selectable_value(&mut self.render_mode, mode, mode.name());
+                        }
+                    });
+                if self.render_mode != previous_mode {
+                    self.shader_dirty = true;
+                }
+            });
+            if self.render_mode == RenderMode::VertexArt {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut self.vertex_art.vertex_count)
+                            .range(1..=1_000_000)
+                            .prefix("Vertices: "),
+                    );
+                    let previous_topology = self.vertex_art.topology;
+                    egui::ComboBox::from_label("Topology")
+                        .selected_text(self.vertex_art.topology.name())
+                        .show_ui(ui, |ui| {
+                            for topology in Topology::ALL {
+                                ui.selectable_value(
+                                    &mut self.vertex_art.topology,
+                                    topology,
+                                    topology.name(),
+                                );
+                            }
+                        });
+                    // The topology is part of the pipeline.
+                    if self.vertex_art.topology != previous_topology {
+                        self.shader_dirty = true;
+                    }
+                });
+            }
+            ui.add_enabled_ui(self.render_mode == RenderMode::Fragment, |ui| {
+                ui.horizontal(|ui| {
+                    let previous_language = self.shader_language;
+                    egui::ComboBox::from_label("Language")
+                        .selected_text(self.shader_language.name())
+                        .show_ui(ui, |ui| {
+                            for language in ShaderLanguage::ALL {
+                                ui.selectable_value(
+                                    &mut self.shader_language,
+                                    language,
+                                    language.name(),
+                                );
+                            }
+                        });
+                    if self.shader_language != previous_language {
+                        // Swap the example shader, but keep anything the user wrote.
+                        if self.shader_content == previous_language.default_content() {
+                            self.shader_content =
+                                self.shader_language.default_content().to_string();
+                        }
+                        self.shader_dirty = true;
+                    }
+                });
+            });
+            ui.collapsing("Compiler options", |ui| {
+                let previous_settings = self.compile_settings.clone();
+                ui.checkbox(
+                    &mut self.compile_settings.warnings_as_errors,
+                    "Warnings as errors",
+                );
+                ui.checkbox(&mut self.compile_settings.debug_info, "Debug info");
+                if ui
+                    .checkbox(&mut self.webgl2_portability, "Check WebGL2 portability")
+                    .on_hover_text("Also compile GLSL under GLSL ES rules, as Shadertoy does")
+                    .changed()
+                {
+                    self.shader_dirty = true;
+                }
+                egui::ComboBox::from_label("Optimization")
+                    .selected_text(self.compile_settings.optimization.name())
+                    .show_ui(ui, |ui| {
+                        for level in OptimizationLevel::ALL {
+                            ui.selectable_value(
+                                &mut self.compile_settings.optimization,
+                                level,
+                                level.name(),
+                            );
+                        }
+                    });
+                egui::ComboBox::from_label("Target")
+                    .selected_text(self.compile_settings.target_env.name())
+                    .show_ui(ui, |ui| {
+                        for target_env in TargetEnv::ALL {
+                            ui.selectable_value(
+                                &mut self.compile_settings.target_env,
+                                target_env,
+                                target_env.name(),
+                            );
+                        }
+                    });
+                if self.compile_settings != previous_settings {
+                    self.shader_dirty = true;
+                }
+            });
+            ui.collapsing("Template", |ui| {
+                let template = &mut self.compile_settings.template;
+                let previous_template = template.clone();
                 #[cfg(not(target_arch = "wasm32"))]
-                ui.checkbox(&mut self.shader_editor, "Shader Editor");
+                ui.horizontal(|ui| {
+                    match &template.path {
+                        Some(path) => ui.label(path.display().to_string()),
+                        None => ui.label("Built-in template"),
+                    };
+                    if ui.button("Choose…").clicked() {
+                        let language = self.shader_language;
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter(language.name(), language.extensions())
+                            .pick_file()
+                        {
+                            template.path = Some(path);
+                        }
+                    }
+                    if ui
+                        .add_enabled(template.path.is_some(), egui::Button::new("Built-in"))
+                        .clicked()
+                    {
+                        template.path = None;
+                    }
+                });
+                ui.label("Defines (NAME or NAME=VALUE per line)");
+                let defines_changed = ui
+                    .add(
+                        egui::TextEdit::multiline(&mut template.defines)
+                            .font(egui::TextStyle::Monospace)
+                            .desired_rows(2),
+                    )
+                    .changed();
+                ui.label("Common code");
+                let common_changed = ui
+                    .add(
+                        egui::TextEdit::multiline(&mut template.common)
+                            .font(egui::TextStyle::Monospace)
+                            .code_editor()
+                            .desired_rows(4),
+                    )
+                    .changed();
+                if *template != previous_template {
+                    self.shader_dirty = true;
+                }
+                if defines_changed || common_changed {
+                    self.shader_edited_at = Some(Instant::now());
+                }
+            });
+            ui.collapsing("Compute passes", |ui| {
+                let mut changed = false;
+                let max_data_len = max_data_len(&self.render_state.device);
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut self.compute.data_len)
+                                .range(1..=max_data_len)
+                                .prefix("iData: ")
+                                .suffix(" vec4"),
+                        )
+                        .changed();
+                    let [width, height] = &mut self.compute.image_size;
+                    ui.label("Image:");
+                    changed |= ui
+                        .add(egui::DragValue::new(width).range(1..=8192))
+                        .changed();
+                    changed |= ui
+                        .add(egui::DragValue::new(height).range(1..=8192))
+                        .changed();
+                });
+                let mut removed = None;
+                for (index, pass) in self.compute.passes.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        changed |= ui
+                            .checkbox(&mut pass.enabled, format!("Pass {}", index + 1))
+                            .changed();
+                        ui.label("Workgroups:");
+                        for count in &mut pass.workgroups {
+                            changed |= ui
+                                .add(egui::DragValue::new(count).range(1..=65535))
+                                .changed();
+                        }
+                        let editing = self.editing_compute_pass == Some(index);
+                        if ui.selectable_label(editing, "Edit").clicked() {
+                            self.editing_compute_pass = (!editing).then_some(index);
+                        }
+                        if ui.button("✖").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = removed {
+                    self.compute.passes.remove(index);
+                    self.editing_compute_pass = None;
+                    changed = true;
+                }
+                if ui.button("Add compute pass").clicked() {
+                    self.compute.passes.push(ComputePass::default());
+                    self.editing_compute_pass = Some(self.compute.passes.len() - 1);
+                    changed = true;
+                }
+                if changed {
+                    self.shader_dirty = true;
+                }
+            });
+            ui.collapsing("Output", |ui| {
+                let output = &mut self.output_settings;
+                let previous_format = output.format;
+                egui::ComboBox::from_label("Format")
+                    .selected_text(output.format.name())
+                    .show_ui(ui, |ui| {
+                        for format in HdrFormat::ALL {
+                            ui.selectable_value(&mut output.format, format, format.name());
+                        }
+                    });
+                // The image pipeline is built for the target format.
+                if output.format != previous_format {
+                    self.shader_dirty = true;
+                }
+                egui::ComboBox::from_label("Tone mapping")
+                    .selected_text(output.tone_mapping.name())
+                    .show_ui(ui, |ui| {
+                        for tone_mapping in ToneMapping::ALL {
+                            ui.selectable_value(
+                                &mut output.tone_mapping,
+                                tone_mapping,
+                                tone_mapping.name(),
+                            );
+                        }
+                    });
+                ui.add(
+                    egui::Slider::new(&mut output.exposure, -8.0..=8.0)
+                        .text("Exposure")
+                        .suffix(" EV"),
+                );
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt("float_export_pass")
+                        .selected_text(self.float_export_pass.name())
+                        .show_ui(ui, |ui| {
+                            // The tone-mapped output goes straight to the
+                            // window and cannot be read back.
+                            for pass in [ExportPass::Image, ExportPass::ComputeImage] {
+                                ui.selectable_value(&mut self.float_export_pass, pass, pass.name());
+                            }
+                        });
+                    if ui
+                        .button("Export EXR/HDR…")
+                        .on_hover_text(
+                            "Writes the pass unclamped, before tone mapping. Use the RGBA32F \
+                             format for full precision; .hdr files drop alpha.",
+                        )
+                        .clicked()
+                    {
+                        let mut dialog = rfd::FileDialog::new();
+                        for format in FloatFormat::ALL {
+                            dialog = dialog.add_filter(format.name(), &[format.extension()]);
+                        }
+                        if let Some(path) = dialog.save_file() {
+                            match self.export_float_image(&path) {
+                                Ok(()) => info!("Wrote {}", path.display()),
+                                Err(err) => {
+                                    error!("Failed to export {}: {:#}", path.display(), err)
+                                }
+                            }
+                        }
+                    }
+                });
+            });
+            ui.collapsing("fragCoord", |ui| {
+                let convention = &mut self.frag_coord_convention;
+                egui::ComboBox::from_label("Origin")
+                    .selected_text(convention.origin.name())
+                    .show_ui(ui, |ui| {
+                        for origin in FragCoordOrigin::ALL {
+                            ui.selectable_value(&mut convention.origin, origin, origin.name());
+                        }
+                    });
+                ui.checkbox(
+                    &mut convention.integer_pixel_centers,
+                    "Pixel centers on whole numbers",
+                );
+            });
+            if let Some(stats) = &self.compile_stats {
+                let format = match self.shader_language {
+                    ShaderLanguage::Wgsl => "WGSL",
+                    ShaderLanguage::Glsl | ShaderLanguage::Hlsl => "SPIR-V",
+                };
+                ui.label(if stats.cached {
+                    format!("{}: {} bytes (cached)", format, stats.size)
+                } else {
+                    format!(
+                        "{}: {} bytes, compiled in {:.1} ms",
+                        format,
+                        stats.size,
+                        stats.compile_time.as_secs_f64() * 1000.0
+                    )
+                });
+            }
+            if !self.diagnostics.is_empty() {
+                let count = |severity| {
+                    self.diagnostics
+                        .iter()
+                        .filter(|diagnostic| diagnostic.severity == severity)
+                        .count()
+                };
+                ui.label(format!(
+                    "{} error(s), {} warning(s) - see the log",
+                    count(Severity::Error),
+                    count(Severity::Warning)
+                ));
+            }
+            ui.horizontal(|ui| {
+                // Only WGSL compiles on the web; GLSL and HLSL need shaderc.
+                let editable =
+                    !cfg!(target_arch = "wasm32") || self.shader_language == ShaderLanguage::Wgsl;
+                if !editable {
+                    self.shader_editor = false;
+                }
+                ui.add_enabled(
+                    editable,
+                    egui::Checkbox::new(&mut self.shader_editor, "Shader Editor"),
+                )
+                .on_disabled_hover_text("Switch to WGSL to edit shaders on the web");
                 ui.checkbox(&mut self.show_logger, "Log");
+                if ui.checkbox(&mut self.show_ir, "IR").changed() {
+                    self.ir_dirty = true;
+                }
             });
 
             #[cfg(not(target_arch = "wasm32"))]
@@ -580,11 +1281,12 @@ impl eframe::App for App {
                 ui.horizontal(|ui| {
                     ui.checkbox(&mut self.monitor_external_file, "Monitor External File");
 
-                    if ui.button("Select GLSL File").clicked() {
-                        if let Some(path) = rfd::FileDialog::new()
-                            .add_filter("GLSL", &["glsl", "frag", "vert"])
-                            .pick_file()
-                        {
+                    if ui.button("Select Shader File").clicked() {
+                        let mut dialog = rfd::FileDialog::new();
+                        for language in ShaderLanguage::ALL {
+                            dialog = dialog.add_filter(language.name(), language.extensions());
+                        }
+                        if let Some(path) = dialog.pick_file() {
                             if let Some(path_str) = path.to_str() {
                                 match self.set_external_glsl_file_watcher(path_str) {
                                     Ok(_) => {
@@ -606,13 +1308,25 @@ impl eframe::App for App {
 
             if self.shader_editor {
                 let theme = egui_extras::syntax_highlighting::CodeTheme::from_style(ui.style());
+                let compute_pass = self
+                    .editing_compute_pass
+                    .and_then(|index| self.compute.passes.get_mut(index));
+                let (content, syntax) = match (compute_pass, self.render_mode) {
+                    (Some(pass), _) => (&mut pass.content, ShaderLanguage::Glsl.syntax()),
+                    (None, RenderMode::Fragment) => {
+                        (&mut self.shader_content, self.shader_language.syntax())
+                    }
+                    (None, RenderMode::VertexArt) => {
+                        (&mut self.vertex_art.content, ShaderLanguage::Glsl.syntax())
+                    }
+                };
                 let mut layouter = |ui: &egui::Ui, string: &str, wrap_width: f32| {
                     let mut layout_job = egui_extras::syntax_highlighting::highlight(
                         ui.ctx(),
                         ui.style(),
                         &theme,
                         string,
-                        "c",
+                        syntax,
                     );
                     layout_job.wrap.max_width = wrap_width;
                     ui.fonts(|f| f.layout_job(layout_job))
@@ -624,7 +1338,7 @@ impl eframe::App for App {
                     .show(ui, |ui| {
                         if ui
                             .add(
-                                egui::TextEdit::multiline(&mut self.shader_content)
+                                egui::TextEdit::multiline(content)
                                     .font(egui::TextStyle::Monospace)
                                     .code_editor()
                                     .lock_focus(true)
@@ -635,6 +1349,7 @@ impl eframe::App for App {
                             .changed()
                         {
                             self.shader_dirty = true;
+                            self.shader_edited_at = Some(Instant::now());
                         }
                     });
             }
@@ -643,10 +1358,16 @@ impl eframe::App for App {
             }
         });
 
+        if let Some(shader_ir) = &mut self.shader_ir {
+            egui::Window::new("Intermediate Representation")
+                .open(&mut self.show_ir)
+                .default_size([600.0, 400.0])
+                .show(ctx, |ui| shader_ir.ui(ui));
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // allocate rect as big as possible
             let rect = ui.available_rect_before_wrap();
-            let (width, height) = (rect.width(), rect.height());
             //let aspect = 4.0 / 3.0;
             //let (width, height) = if rect.width() / rect.height() > aspect {
             //    (rect.height() * aspect, rect.height())
@@ -655,12 +1376,35 @@ impl eframe::App for App {
             //};
             //rect.set_width(width);
             //rect.set_height(height);
-            self.wgpu_callback.uniform.resolution = std140::vec2(
-                width * ctx.pixels_per_point(),
-                height * ctx.pixels_per_point(),
-            );
-            self.wgpu_callback.uniform.time =
-                std140::float(Instant::now().duration_since(self.start_time).as_secs_f32());
+            // Round the same way egui does when it sets the viewport, so the
+            // offscreen image maps onto whole pixels.
+            let pixels_per_point = ctx.pixels_per_point();
+            let viewport_min = (rect.min.to_vec2() * pixels_per_point).round();
+            let viewport_size = (rect.max.to_vec2() * pixels_per_point).round() - viewport_min;
+            self.wgpu_callback.uniform.resolution = std140::vec2(viewport_size.x, viewport_size.y);
+            // The image pass renders into its own target, so the viewport
+            // starts at its corner.
+            let [scale_x, scale_y, offset_x, offset_y] = self
+                .frag_coord_convention
+                .transform([0.0, 0.0], viewport_size.into());
+            self.wgpu_callback.output = self.output_settings;
+            self.wgpu_callback.uniform.frag_coord_transform =
+                std140::vec4(scale_x, scale_y, offset_x, offset_y);
+            self.wgpu_callback.vertex_count = match self.pipeline_mode {
+                RenderMode::Fragment => 6,
+                RenderMode::VertexArt => self.vertex_art.vertex_count,
+            };
+            self.wgpu_callback.uniform.vertex_count =
+                std140::float(self.wgpu_callback.vertex_count as f32);
+            let time = Instant::now().duration_since(self.start_time).as_secs_f32();
+            // The measured frame interval, as exports take it from their clock.
+            let time_delta = if self.wgpu_callback.uniform.frame.0 == 0.0 {
+                0.0
+            } else {
+                (time - self.wgpu_callback.uniform.time.0).max(0.0)
+            };
+            self.wgpu_callback.uniform.time_delta = std140::float(time_delta);
+            self.wgpu_callback.uniform.time = std140::float(time);
             ui.painter().add(egui_wgpu::Callback::new_paint_callback(
                 rect,
                 self.wgpu_callback.clone(),
@@ -671,5 +1415,31 @@ impl eframe::App for App {
     }
 
     /// Called by the frame work to save state before shutdown.
-    fn save(&mut self, _storage: &mut dyn eframe::Storage) {}
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.remember_compile_settings();
+            eframe::set_value(
+                storage,
+                COMPILE_SETTINGS_KEY,
+                &self.default_compile_settings,
+            );
+            eframe::set_value(
+                storage,
+                PROJECT_COMPILE_SETTINGS_KEY,
+                &self.project_compile_settings,
+            );
+        }
+        #[cfg(target_arch = "wasm32")]
+        eframe::set_value(storage, COMPILE_SETTINGS_KEY, &self.compile_settings);
+        eframe::set_value(storage, WEBGL2_PORTABILITY_KEY, &self.webgl2_portability);
+        eframe::set_value(storage, RENDER_MODE_KEY, &self.render_mode);
+        eframe::set_value(storage, VERTEX_ART_KEY, &self.vertex_art);
+        eframe::set_value(
+            storage,
+            FRAG_COORD_CONVENTION_KEY,
+            &self.frag_coord_convention,
+        );
+        eframe::set_value(storage, OUTPUT_SETTINGS_KEY, &self.output_settings);
+    }
 }
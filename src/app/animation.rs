@@ -0,0 +1,590 @@
+//! Animated image export: GIF, APNG and animated WebP, written frame by frame
+//! as the headless renderer produces them.
+
+use crate::app::{Result, RgbaImage};
+use anyhow::{anyhow, bail, Context as _};
+use color_quant::NeuQuant;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    WebP,
+}
+
+impl AnimationFormat {
+    pub const ALL: [Self; 3] = [Self::Gif, Self::Apng, Self::WebP];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gif => "GIF",
+            Self::Apng => "APNG",
+            Self::WebP => "WebP",
+        }
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Gif => &["gif"],
+            Self::Apng => &["png", "apng"],
+            Self::WebP => &["webp"],
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extensions().contains(&extension.as_str()))
+    }
+}
+
+/// How GIF quantization error is spread to neighbouring pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    #[default]
+    FloydSteinberg,
+}
+
+impl Dither {
+    pub const ALL: [Self; 2] = [Self::None, Self::FloydSteinberg];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::FloydSteinberg => "floyd-steinberg",
+        }
+    }
+}
+
+/// Which frames a GIF palette is trained on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GifPalette {
+    /// One palette trained on the first frame and written once as the
+    /// global colour table; smaller and does not flicker, but later frames
+    /// with new colours suffer.
+    #[default]
+    Global,
+    /// A palette per frame.
+    PerFrame,
+}
+
+impl GifPalette {
+    pub const ALL: [Self; 2] = [Self::Global, Self::PerFrame];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::PerFrame => "per-frame",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GifOptions {
+    /// Palette size, 2 to 256.
+    pub colors: usize,
+    pub dither: Dither,
+    pub palette: GifPalette,
+    /// NeuQuant's sampling factor, 1 (best, slowest) to 30 (fastest).
+    pub sample_factor: i32,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            colors: 256,
+            dither: Dither::default(),
+            palette: GifPalette::default(),
+            sample_factor: 10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationOptions {
+    pub fps: f64,
+    /// How many times the animation plays; 0 loops forever.
+    pub plays: u16,
+    pub gif: GifOptions,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self {
+            fps: 30.0,
+            plays: 0,
+            gif: GifOptions::default(),
+        }
+    }
+}
+
+/// Duration of frame `index` in `units` per second. Rounding the frame's end
+/// rather than its length keeps the total in step with `fps` when a frame is
+/// not a whole number of units.
+fn frame_delay(index: u32, fps: f64, units: f64) -> u32 {
+    let end = |frame: u32| (f64::from(frame) * units / fps).round() as u32;
+    end(index + 1) - end(index)
+}
+
+/// Writes the header of a `size` GIF with the global colour table `palette`.
+fn gif_encoder<W: Write>(
+    writer: W,
+    size: [u32; 2],
+    palette: &[u8],
+    plays: u16,
+) -> Result<gif::Encoder<W>> {
+    let mut encoder = gif::Encoder::new(writer, size[0] as u16, size[1] as u16, palette)?;
+    encoder.set_repeat(match plays {
+        0 => gif::Repeat::Infinite,
+        plays => gif::Repeat::Finite(plays - 1),
+    })?;
+    Ok(encoder)
+}
+
+enum Encoder<W: Write> {
+    Gif {
+        /// Held until the first frame, whose palette becomes the global one
+        /// when the encoder writes the header.
+        writer: Option<W>,
+        encoder: Option<gif::Encoder<W>>,
+        /// The global palette's quantizer, once trained.
+        quantizer: Option<NeuQuant>,
+    },
+    Apng(png::Writer<W>),
+    WebP {
+        writer: W,
+        /// `ANMF` chunks, written out with the header once the total size is
+        /// known.
+        frames: Vec<u8>,
+    },
+}
+
+/// Encodes an animation one frame at a time.
+pub struct AnimationWriter<W: Write> {
+    encoder: Encoder<W>,
+    size: [u32; 2],
+    options: AnimationOptions,
+    frame: u32,
+}
+
+impl AnimationWriter<std::io::BufWriter<std::fs::File>> {
+    /// Creates `path`, in the format its extension names.
+    pub fn create(
+        path: &Path,
+        size: [u32; 2],
+        frame_count: u32,
+        options: &AnimationOptions,
+    ) -> Result<Self> {
+        let format = AnimationFormat::from_path(path).ok_or_else(|| {
+            anyhow!(
+                "Unknown animation format for {}; use .gif, .png/.apng or .webp",
+                path.display()
+            )
+        })?;
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Self::new(
+            std::io::BufWriter::new(file),
+            format,
+            size,
+            frame_count,
+            options,
+        )
+    }
+}
+
+impl<W: Write> AnimationWriter<W> {
+    /// `frame_count` is needed up front by APNG, and must match the number of
+    /// frames written.
+    pub fn new(
+        writer: W,
+        format: AnimationFormat,
+        size: [u32; 2],
+        frame_count: u32,
+        options: &AnimationOptions,
+    ) -> Result<Self> {
+        if !(2..=256).contains(&options.gif.colors) {
+            bail!("A GIF palette has 2 to 256 colours");
+        }
+        let encoder = match format {
+            AnimationFormat::Gif => {
+                if size.iter().any(|&side| side > u32::from(u16::MAX)) {
+                    bail!("GIFs are at most 65535 pixels wide and high");
+                }
+                Encoder::Gif {
+                    writer: Some(writer),
+                    encoder: None,
+                    quantizer: None,
+                }
+            }
+            AnimationFormat::Apng => {
+                let mut encoder = png::Encoder::new(writer, size[0], size[1]);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frame_count, u32::from(options.plays))?;
+                Encoder::Apng(encoder.write_header()?)
+            }
+            AnimationFormat::WebP => Encoder::WebP {
+                writer,
+                frames: Vec::new(),
+            },
+        };
+        Ok(Self {
+            encoder,
+            size,
+            options: *options,
+            frame: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, image: &RgbaImage) -> Result<()> {
+        if [image.width, image.height] != self.size {
+            bail!(
+                "Frame is {}x{}, the animation {}x{}",
+                image.width,
+                image.height,
+                self.size[0],
+                self.size[1]
+            );
+        }
+        let fps = self.options.fps;
+        match &mut self.encoder {
+            Encoder::Gif {
+                writer,
+                encoder,
+                quantizer,
+            } => {
+                let gif = &self.options.gif;
+                let trained;
+                let quantizer = match (gif.palette, quantizer) {
+                    (GifPalette::Global, Some(quantizer)) => &*quantizer,
+                    (GifPalette::Global, quantizer) => quantizer.insert(NeuQuant::new(
+                        gif.sample_factor,
+                        gif.colors,
+                        &image.pixels,
+                    )),
+                    (GifPalette::PerFrame, _) => {
+                        trained = NeuQuant::new(gif.sample_factor, gif.colors, &image.pixels);
+                        &trained
+                    }
+                };
+                let palette = quantizer.color_map_rgb();
+                let encoder = match (encoder, writer.take()) {
+                    (Some(encoder), _) => encoder,
+                    (encoder, Some(writer)) => {
+                        let global = match gif.palette {
+                            GifPalette::Global => palette.as_slice(),
+                            GifPalette::PerFrame => &[],
+                        };
+                        encoder.insert(gif_encoder(writer, self.size, global, self.options.plays)?)
+                    }
+                    (None, None) => unreachable!("the GIF writer is taken with the first frame"),
+                };
+                let indices = quantize(image, quantizer, gif.dither);
+                let mut frame = gif::Frame::from_palette_pixels(
+                    image.width as u16,
+                    image.height as u16,
+                    indices,
+                    palette,
+                    None,
+                );
+                if gif.palette == GifPalette::Global {
+                    frame.palette = None;
+                }
+                frame.delay = frame_delay(self.frame, fps, 100.0) as u16;
+                encoder.write_frame(&frame)?;
+            }
+            Encoder::Apng(writer) => {
+                // Exact when the rate is a whole number of frames per second.
+                if fps.fract() == 0.0 && fps <= f64::from(u16::MAX) {
+                    writer.set_frame_delay(1, fps as u16)?;
+                } else {
+                    writer.set_frame_delay(frame_delay(self.frame, fps, 1000.0) as u16, 1000)?;
+                }
+                writer.write_image_data(&rgb_pixels(image))?;
+            }
+            Encoder::WebP { frames, .. } => {
+                let mut still = Vec::new();
+                image_webp::WebPEncoder::new(&mut still).encode(
+                    &rgb_pixels(image),
+                    image.width,
+                    image.height,
+                    image_webp::ColorType::Rgb8,
+                )?;
+                // A simple-format still is `RIFF <size> WEBP` followed by one
+                // VP8L chunk, which becomes the frame's bitstream.
+                let bitstream = &still[12..];
+                let mut anmf = Vec::with_capacity(16 + bitstream.len());
+                anmf.extend_from_slice(&[0; 6]); // frame offset, in units of 2
+                anmf.extend_from_slice(&(image.width - 1).to_le_bytes()[..3]);
+                anmf.extend_from_slice(&(image.height - 1).to_le_bytes()[..3]);
+                anmf.extend_from_slice(&frame_delay(self.frame, fps, 1000.0).to_le_bytes()[..3]);
+                // Frames are opaque and replace the canvas: no blending, no
+                // disposal.
+                anmf.push(0b10);
+                anmf.extend_from_slice(bitstream);
+                write_riff_chunk(frames, b"ANMF", &anmf)?;
+            }
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.encoder {
+            Encoder::Gif {
+                writer, encoder, ..
+            } => {
+                let encoder = match (encoder, writer) {
+                    (Some(encoder), _) => encoder,
+                    // Without frames there is no palette to write.
+                    (None, Some(writer)) => {
+                        gif_encoder(writer, self.size, &[], self.options.plays)?
+                    }
+                    (None, None) => unreachable!("the GIF writer is taken with the first frame"),
+                };
+                encoder.into_inner()?.flush()?;
+            }
+            Encoder::Apng(writer) => writer.finish()?,
+            Encoder::WebP { mut writer, frames } => {
+                let mut vp8x = Vec::with_capacity(10);
+                vp8x.push(0b10); // animation
+                vp8x.extend_from_slice(&[0; 3]);
+                vp8x.extend_from_slice(&(self.size[0] - 1).to_le_bytes()[..3]);
+                vp8x.extend_from_slice(&(self.size[1] - 1).to_le_bytes()[..3]);
+                let mut anim = Vec::with_capacity(6);
+                anim.extend_from_slice(&[0, 0, 0, 255]); // opaque black background
+                anim.extend_from_slice(&self.options.plays.to_le_bytes());
+
+                let mut body = b"WEBP".to_vec();
+                write_riff_chunk(&mut body, b"VP8X", &vp8x)?;
+                write_riff_chunk(&mut body, b"ANIM", &anim)?;
+                body.extend_from_slice(&frames);
+                let size = u32::try_from(body.len())
+                    .map_err(|_| anyhow!("The animation is too large for WebP"))?;
+                writer.write_all(b"RIFF")?;
+                writer.write_all(&size.to_le_bytes())?;
+                writer.write_all(&body)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_riff_chunk(writer: &mut impl Write, id: &[u8; 4], data: &[u8]) -> Result<()> {
+    let size =
+        u32::try_from(data.len()).map_err(|_| anyhow!("A WebP frame is too large to store"))?;
+    writer.write_all(id)?;
+    writer.write_all(&size.to_le_bytes())?;
+    writer.write_all(data)?;
+    if data.len() % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+/// Drops the alpha channel; rendered frames are opaque.
+fn rgb_pixels(image: &RgbaImage) -> Vec<u8> {
+    image
+        .pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect()
+}
+
+/// Maps every pixel to a palette index, diffusing the error if asked to.
+fn quantize(image: &RgbaImage, quantizer: &NeuQuant, dither: Dither) -> Vec<u8> {
+    let width = image.width as usize;
+    let mut indices = Vec::with_capacity(image.pixels.len() / 4);
+    match dither {
+        Dither::None => {
+            for pixel in image.pixels.chunks_exact(4) {
+                indices.push(quantizer.index_of(pixel) as u8);
+            }
+        }
+        Dither::FloydSteinberg => {
+            // Error carried into the current and the next row.
+            let mut current = vec![[0.0f32; 3]; width + 2];
+            let mut next = vec![[0.0f32; 3]; width + 2];
+            for row in image.pixels.chunks_exact(width * 4) {
+                for (x, pixel) in row.chunks_exact(4).enumerate() {
+                    let mut wanted = [0.0f32; 3];
+                    let mut target = [0u8, 0, 0, 255];
+                    for channel in 0..3 {
+                        wanted[channel] = f32::from(pixel[channel]) + current[x + 1][channel];
+                        target[channel] = wanted[channel].round().clamp(0.0, 255.0) as u8;
+                    }
+                    let index = quantizer.index_of(&target);
+                    indices.push(index as u8);
+                    let chosen = quantizer.lookup(index).unwrap_or(target);
+                    for channel in 0..3 {
+                        let error = wanted[channel] - f32::from(chosen[channel]);
+                        current[x + 2][channel] += error * 7.0 / 16.0;
+                        next[x][channel] += error * 3.0 / 16.0;
+                        next[x + 1][channel] += error * 5.0 / 16.0;
+                        next[x + 2][channel] += error * 1.0 / 16.0;
+                    }
+                }
+                std::mem::swap(&mut current, &mut next);
+                next.fill([0.0; 3]);
+            }
+        }
+    }
+    indices
+}
+
+/// Mean absolute difference between two images' colour channels, from 0
+/// (identical) to 1.
+pub fn image_difference(a: &RgbaImage, b: &RgbaImage) -> f64 {
+    let total: u64 = a
+        .pixels
+        .chunks_exact(4)
+        .zip(b.pixels.chunks_exact(4))
+        .flat_map(|(a, b)| (0..3).map(move |channel| u64::from(a[channel].abs_diff(b[channel]))))
+        .sum();
+    let samples = (a.pixels.len() / 4 * 3).max(1);
+    total as f64 / samples as f64 / 255.0
+}
+
+/// Checks whether an animation loops without a visible jump, by comparing the
+/// change from its last frame back to its first with the typical change
+/// between consecutive frames.
+#[derive(Default)]
+pub struct LoopCheck {
+    first: Option<RgbaImage>,
+    previous: Option<RgbaImage>,
+    step_total: f64,
+    steps: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoopReport {
+    /// Difference between the last and the first frame.
+    pub seam: f64,
+    /// Mean difference between consecutive frames.
+    pub mean_step: f64,
+}
+
+impl LoopReport {
+    /// Whether the wrap-around is no more abrupt than an ordinary frame
+    /// step, with one 8-bit level of slack for still images.
+    pub fn is_seamless(&self) -> bool {
+        self.seam <= self.mean_step * 1.5 + 1.0 / 255.0
+    }
+}
+
+impl LoopCheck {
+    pub fn add_frame(&mut self, image: &RgbaImage) {
+        if let Some(previous) = &self.previous {
+            self.step_total += image_difference(previous, image);
+            self.steps += 1;
+        }
+        if self.first.is_none() {
+            self.first = Some(image.clone());
+        }
+        self.previous = Some(image.clone());
+    }
+
+    /// `None` until two frames were added.
+    pub fn report(&self) -> Option<LoopReport> {
+        let (first, last) = (self.first.as_ref()?, self.previous.as_ref()?);
+        (self.steps > 0).then(|| LoopReport {
+            seam: image_difference(last, first),
+            mean_step: self.step_total / f64::from(self.steps),
+        })
+    }
+}
+
+#[cfg(test)]
+fn gradient(size: u32, shift: u32) -> RgbaImage {
+    let pixels = (0..size * size)
+        .flat_map(|index| {
+            let value = ((index % size + shift) * 255 / (size * 2)) as u8;
+            [value, 255 - value, value / 2, 255]
+        })
+        .collect();
+    RgbaImage {
+        width: size,
+        height: size,
+        pixels,
+    }
+}
+
+#[test]
+fn frame_delays_keep_the_frame_rate() {
+    let delays: Vec<u32> = (0..3)
+        .map(|frame| frame_delay(frame, 60.0, 100.0))
+        .collect();
+    assert_eq!(delays, [2, 1, 2]);
+    let total: u32 = (0..60).map(|frame| frame_delay(frame, 60.0, 100.0)).sum();
+    assert_eq!(total, 100);
+}
+
+#[test]
+fn webp_animation_is_readable() {
+    let mut output = Vec::new();
+    let mut writer = AnimationWriter::new(
+        &mut output,
+        AnimationFormat::WebP,
+        [16, 16],
+        3,
+        &AnimationOptions::default(),
+    )
+    .unwrap();
+    for shift in 0..3 {
+        writer.write_frame(&gradient(16, shift)).unwrap();
+    }
+    writer.finish().unwrap();
+    let decoder = image_webp::WebPDecoder::new(std::io::Cursor::new(output)).unwrap();
+    assert!(decoder.is_animated());
+    assert_eq!(decoder.num_frames(), 3);
+}
+
+#[test]
+fn global_gif_palette_is_written_once() {
+    for (palette, global) in [(GifPalette::Global, true), (GifPalette::PerFrame, false)] {
+        let mut options = AnimationOptions::default();
+        options.gif.palette = palette;
+        let mut output = Vec::new();
+        let mut writer =
+            AnimationWriter::new(&mut output, AnimationFormat::Gif, [16, 16], 3, &options).unwrap();
+        for shift in 0..3 {
+            writer.write_frame(&gradient(16, shift)).unwrap();
+        }
+        writer.finish().unwrap();
+        let mut decode = gif::DecodeOptions::new();
+        decode.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = decode.read_info(std::io::Cursor::new(output)).unwrap();
+        // The gif crate pads an empty global palette to two colours.
+        let global_colors = decoder
+            .global_palette()
+            .map_or(0, |palette| palette.len() / 3);
+        assert_eq!(global_colors > 2, global);
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.palette.is_some(), !global);
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+    }
+}
+
+#[test]
+fn loop_check() {
+    let mut check = LoopCheck::default();
+    for shift in [0, 1, 2, 1] {
+        check.add_frame(&gradient(16, shift));
+    }
+    assert!(check.report().unwrap().is_seamless());
+
+    let mut check = LoopCheck::default();
+    for shift in [0, 1, 2, 3, 4, 5, 6] {
+        check.add_frame(&gradient(16, shift));
+    }
+    assert!(!check.report().unwrap().is_seamless());
+}
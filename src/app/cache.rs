@@ -0,0 +1,450 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Default number of bytes of SPIR-V kept in memory.
+pub const DEFAULT_MEMORY_LIMIT: usize = 32 * 1024 * 1024;
+/// Default number of bytes of SPIR-V kept in the on-disk cache directory.
+pub const DEFAULT_DISK_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// FNV-1a, used instead of `DefaultHasher` because its output must stay stable
+/// across builds for the on-disk cache to be reusable.
+struct Fnv64(u64);
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv64 {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// First word of every SPIR-V module.
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Identifies the compiler, so entries written by another shaderc or another
+/// version of the app are not reused.
+fn compiler_version() -> String {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let (version, revision) = shaderc::get_spirv_version();
+        format!(
+            "{} shaderc spirv {}.{}",
+            env!("CARGO_PKG_VERSION"),
+            version,
+            revision
+        )
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        env!("CARGO_PKG_VERSION").to_owned()
+    }
+}
+
+/// Hash identifying one compilation: the compiler, the template, the user
+/// source, the preprocessor defines and everything in the compiler options
+/// that changes the generated SPIR-V.
+pub fn cache_key(template: &str, source: &str, defines: &str, options: &impl Hash) -> u64 {
+    let mut hasher = Fnv64::default();
+    compiler_version().hash(&mut hasher);
+    template.hash(&mut hasher);
+    source.hash(&mut hasher);
+    defines.hash(&mut hasher);
+    options.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// SPIR-V and the compiler output that came with it.
+#[derive(Clone)]
+pub struct SpirvModule {
+    pub spirv: Arc<[u32]>,
+    /// Raw compiler warnings, kept so a cache hit reports the same warnings
+    /// as the original compile.
+    pub warnings: Arc<str>,
+}
+
+impl SpirvModule {
+    fn size(&self) -> usize {
+        std::mem::size_of_val(&*self.spirv) + self.warnings.len()
+    }
+}
+
+/// SPIR-V cache keyed by [`cache_key`], kept in memory and, on native, mirrored
+/// to disk so reopening a project does not recompile every shader.
+pub struct ShaderCache {
+    memory: HashMap<u64, SpirvModule>,
+    /// Keys in insertion order, oldest first, used for eviction.
+    order: VecDeque<u64>,
+    memory_size: usize,
+    memory_limit: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    disk_dir: Option<PathBuf>,
+    #[cfg(not(target_arch = "wasm32"))]
+    disk_limit: u64,
+    /// Bytes in the cache directory, counted on the first write and kept up
+    /// to date after that, so the directory is only listed to trim it.
+    #[cfg(not(target_arch = "wasm32"))]
+    disk_usage: Option<u64>,
+    hits: usize,
+    misses: usize,
+}
+
+impl Default for ShaderCache {
+    fn default() -> Self {
+        Self {
+            memory: HashMap::new(),
+            order: VecDeque::new(),
+            memory_size: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_dir: eframe::storage_dir("shadertoy_rs").map(|dir| dir.join("shader_cache")),
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_limit: DEFAULT_DISK_LIMIT,
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_usage: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl ShaderCache {
+    /// A cache that never touches the disk.
+    pub fn in_memory() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            disk_dir: None,
+            ..Self::default()
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_disk_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_disk_limit(mut self, bytes: u64) -> Self {
+        self.disk_limit = bytes;
+        self
+    }
+
+    pub fn get(&mut self, key: u64) -> Option<SpirvModule> {
+        if let Some(shader) = self.memory.get(&key) {
+            self.hits += 1;
+            return Some(shader.clone());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(shader) = self.read_disk(key) {
+            self.hits += 1;
+            self.insert_memory(key, shader.clone());
+            return Some(shader);
+        }
+        self.misses += 1;
+        None
+    }
+
+    pub fn insert(&mut self, key: u64, shader: SpirvModule) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Err(err) = self.write_disk(key, &shader) {
+            log::warn!("Failed to write shader cache entry: {}", err);
+        }
+        self.insert_memory(key, shader);
+    }
+
+    /// Looks `key` up and falls back to `compile`, caching its result.
+    pub fn get_or_compile(
+        &mut self,
+        key: u64,
+        compile: impl FnOnce() -> crate::app::Result<SpirvModule>,
+    ) -> crate::app::Result<SpirvModule> {
+        if let Some(shader) = self.get(key) {
+            return Ok(shader);
+        }
+        let shader = compile()?;
+        self.insert(key, shader.clone());
+        Ok(shader)
+    }
+
+    /// Drops every entry, both in memory and on disk. The hit and miss counts
+    /// are kept.
+    pub fn clear(&mut self) -> crate::app::Result<()> {
+        self.memory.clear();
+        self.order.clear();
+        self.memory_size = 0;
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(dir) = &self.disk_dir {
+            self.disk_usage = None;
+            if dir.exists() {
+                std::fs::remove_dir_all(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty()
+    }
+
+    /// Bytes of SPIR-V and warnings currently held in memory.
+    pub fn memory_size(&self) -> usize {
+        self.memory_size
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    fn insert_memory(&mut self, key: u64, shader: SpirvModule) {
+        let size = shader.size();
+        if let Some(old) = self.memory.insert(key, shader) {
+            self.memory_size -= old.size();
+            self.order.retain(|k| *k != key);
+        }
+        self.memory_size += size;
+        self.order.push_back(key);
+        while self.memory_size > self.memory_limit && self.order.len() > 1 {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = self.memory.remove(&oldest) {
+                self.memory_size -= evicted.size();
+            }
+        }
+    }
+
+    /// Path of the SPIR-V for `key`; the warnings live next to it with a
+    /// `.log` extension.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn entry_path(&self, key: u64) -> Option<PathBuf> {
+        self.disk_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{key:016x}.spv")))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read_disk(&self, key: u64) -> Option<SpirvModule> {
+        let path = self.entry_path(key)?;
+        let bytes = std::fs::read(&path).ok()?;
+        // Anything that is not a whole module with a SPIR-V header is left
+        // over from a crash or a foreign file; it is recompiled and replaced.
+        if bytes.len() < 20 || bytes.len() % 4 != 0 {
+            return None;
+        }
+        let spirv = bytemuck::pod_collect_to_vec::<u8, u32>(&bytes);
+        if spirv[0] != SPIRV_MAGIC {
+            return None;
+        }
+        let warnings = std::fs::read_to_string(path.with_extension("log")).unwrap_or_default();
+        Some(SpirvModule {
+            spirv: spirv.into(),
+            warnings: warnings.into(),
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn write_disk(&mut self, key: u64, shader: &SpirvModule) -> crate::app::Result<()> {
+        let (Some(dir), Some(path)) = (self.disk_dir.clone(), self.entry_path(key)) else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(&dir)?;
+        let mut written = 0;
+        if !shader.warnings.is_empty() {
+            write_atomic(&path.with_extension("log"), shader.warnings.as_bytes())?;
+            written += shader.warnings.len() as u64;
+        }
+        // The SPIR-V goes last, so a reader never sees it without its warnings.
+        let bytes = bytemuck::cast_slice::<u32, u8>(&shader.spirv);
+        write_atomic(&path, bytes)?;
+        written += bytes.len() as u64;
+
+        let usage = match self.disk_usage {
+            Some(usage) => usage + written,
+            None => disk_entries(&dir)?.iter().map(|(_, len, _)| len).sum(),
+        };
+        self.disk_usage = Some(usage);
+        if usage > self.disk_limit {
+            self.disk_usage = Some(self.trim_disk(&dir)?);
+        }
+        Ok(())
+    }
+
+    /// Deletes the least recently written entries until the directory fits
+    /// within `disk_limit`, returning the bytes left.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn trim_disk(&self, dir: &std::path::Path) -> crate::app::Result<u64> {
+        let mut entries = disk_entries(dir)?;
+        let mut total = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort_by_key(|(modified, ..)| *modified);
+        for (_, len, path) in entries {
+            if total <= self.disk_limit {
+                break;
+            }
+            // Another process may have trimmed it already.
+            if std::fs::remove_file(path).is_ok() {
+                total -= len;
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// The files in the cache directory with their modification times and sizes.
+#[cfg(not(target_arch = "wasm32"))]
+fn disk_entries(
+    dir: &std::path::Path,
+) -> crate::app::Result<Vec<(std::time::SystemTime, u64, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_file() {
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+    }
+    Ok(entries)
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it into
+/// place, so other processes sharing the cache never read a partial file.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_atomic(path: &std::path::Path, bytes: &[u8]) -> crate::app::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let temporary = path.with_extension(format!(
+        "tmp{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&temporary, bytes)?;
+    if let Err(err) = std::fs::rename(&temporary, path) {
+        let _ = std::fs::remove_file(&temporary);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_module(words: &[u32]) -> SpirvModule {
+    SpirvModule {
+        spirv: [&[SPIRV_MAGIC, 0x0001_0000, 0, 1, 0][..], words]
+            .concat()
+            .into(),
+        warnings: "".into(),
+    }
+}
+
+#[cfg(test)]
+fn test_disk_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "shadertoy_rs_cache_{}_{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn oldest_entries_are_evicted_first() {
+    let module_size = test_module(&[]).size();
+    let mut cache = ShaderCache::in_memory().with_memory_limit(2 * module_size);
+    cache.insert(1, test_module(&[]));
+    cache.insert(2, test_module(&[]));
+    // Looking an entry up does not make it newer.
+    assert!(cache.get(1).is_some());
+    cache.insert(3, test_module(&[]));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(1).is_none());
+    assert!(cache.get(2).is_some() && cache.get(3).is_some());
+    assert_eq!((cache.hits(), cache.misses()), (3, 1));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn disk_round_trip() {
+    let dir = test_disk_dir("round_trip");
+    let module = SpirvModule {
+        warnings: "shader.frag:3: warning: unused".into(),
+        ..test_module(&[7, 8, 9])
+    };
+    ShaderCache::in_memory()
+        .with_disk_dir(&dir)
+        .insert(42, module.clone());
+    let mut reopened = ShaderCache::in_memory().with_disk_dir(&dir);
+    let read = reopened.get(42).expect("the entry is on disk");
+    assert_eq!(read.spirv, module.spirv);
+    assert_eq!(read.warnings, module.warnings);
+    // No temporary files are left behind.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn corrupt_disk_entries_are_rejected() {
+    let dir = test_disk_dir("corrupt");
+    let mut cache = ShaderCache::in_memory().with_disk_dir(&dir);
+    cache.insert(1, test_module(&[7, 8, 9]));
+    let path = cache.entry_path(1).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+
+    // Truncated to a whole number of words, as an interrupted write might be.
+    std::fs::write(&path, &bytes[..8]).unwrap();
+    assert!(ShaderCache::in_memory()
+        .with_disk_dir(&dir)
+        .get(1)
+        .is_none());
+    // Not SPIR-V at all.
+    std::fs::write(&path, vec![0u8; bytes.len()]).unwrap();
+    assert!(ShaderCache::in_memory()
+        .with_disk_dir(&dir)
+        .get(1)
+        .is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn disk_is_trimmed_to_its_limit() {
+    let dir = test_disk_dir("trim");
+    let entry_size = std::mem::size_of_val(&*test_module(&[]).spirv) as u64;
+    let mut cache = ShaderCache::in_memory()
+        .with_disk_dir(&dir)
+        .with_disk_limit(2 * entry_size);
+    for key in 0..4 {
+        cache.insert(key, test_module(&[]));
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,156 @@
+//! Compile-only checking of shader files, for linting shader repositories in
+//! CI. Files go through the same templates, shaderc and naga validation as in
+//! the app, but no GPU is needed.
+
+use crate::app::{
+    load_compute_shader, load_fragment_shader, validate_spirv, CompileSettings, Diagnostic, Result,
+    Severity, ShaderCache, ShaderLanguage,
+};
+use anyhow::anyhow;
+use std::path::{Path, PathBuf};
+
+/// Template a checked file is compiled into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PassKind {
+    Image,
+    /// GLSL compute passes, recognised by the `.comp` extension.
+    Compute,
+}
+
+impl PassKind {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("comp") => Self::Compute,
+            _ => Self::Image,
+        }
+    }
+}
+
+/// Outcome of checking one file.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct CheckResult {
+    pub path: PathBuf,
+    pub kind: PassKind,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckResult {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .count()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.count(Severity::Error) == 0
+    }
+}
+
+/// Reads, compiles and validates the shader at `path`.
+pub fn check_file(path: &Path, settings: &CompileSettings, cache: &mut ShaderCache) -> CheckResult {
+    let kind = PassKind::from_path(path);
+    let mut diagnostics = Vec::new();
+    let result = std::fs::read_to_string(path)
+        .map_err(|err| anyhow!("Failed to read {}: {}", path.display(), err))
+        .and_then(|content| {
+            let language = match kind {
+                PassKind::Image => ShaderLanguage::from_path(path)
+                    .ok_or_else(|| anyhow!("Unknown shader language for {}", path.display()))?,
+                PassKind::Compute => ShaderLanguage::Glsl,
+            };
+            check_source(&content, kind, language, settings, cache, &mut diagnostics)
+        });
+    if let Err(err) = result {
+        diagnostics.extend(Diagnostic::from_error(&err));
+    }
+    CheckResult {
+        path: path.to_owned(),
+        kind,
+        diagnostics,
+    }
+}
+
+/// Compiles `content` as `kind` and validates the result with naga. Warnings
+/// are pushed to `diagnostics`; the first error is returned.
+pub fn check_source(
+    content: &str,
+    kind: PassKind,
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    match kind {
+        PassKind::Image => {
+            load_fragment_shader(content, language, settings, cache, diagnostics)?.validate()?;
+        }
+        PassKind::Compute => {
+            validate_spirv(&load_compute_shader(content, settings, cache, diagnostics)?)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn check_reports_errors() {
+    let settings = CompileSettings::default();
+    let mut cache = ShaderCache::in_memory();
+    let mut diagnostics = Vec::new();
+    let good = "void mainImage(out vec4 fragColor, in vec2 fragCoord) {\n\
+                fragColor = vec4(fragCoord / iResolution.xy, 0.0, 1.0);\n}\n";
+    check_source(
+        good,
+        PassKind::Image,
+        ShaderLanguage::Glsl,
+        &settings,
+        &mut cache,
+        &mut diagnostics,
+    )
+    .unwrap();
+    assert!(diagnostics.is_empty());
+
+    let bad = good.replace("iResolution", "iResolutoin");
+    let err = check_source(
+        &bad,
+        PassKind::Image,
+        ShaderLanguage::Glsl,
+        &settings,
+        &mut cache,
+        &mut diagnostics,
+    )
+    .unwrap_err();
+    assert!(
+        Diagnostic::from_error(&err)
+            .iter()
+            .any(|diagnostic| diagnostic.message.contains("iResolutoin")
+                && diagnostic.line == Some(2))
+    );
+
+    let rejected = check_file(
+        Path::new("src/app/test/naga/fail/double_precision.glsl"),
+        &settings,
+        &mut cache,
+    );
+    assert!(!rejected.passed());
+}
+
+#[test]
+fn wgsl_errors_count_lines_from_the_content() {
+    let content = "fn mainImage(fragColor: ptr<function, vec4<f32>>, fragCoord: vec2<f32>) {\n\
+                   \x20   *fragColor = vec4<f32>(fragCoord, 0.0, );\n\
+                   \x20   let x = ;\n}\n";
+    let err = check_source(
+        content,
+        PassKind::Image,
+        ShaderLanguage::Wgsl,
+        &CompileSettings::default(),
+        &mut ShaderCache::in_memory(),
+        &mut Vec::new(),
+    )
+    .unwrap_err();
+    let diagnostics = Diagnostic::from_error(&err);
+    assert_eq!(diagnostics[0].line, Some(3));
+    assert_eq!(diagnostics[0].column, Some(13));
+}
@@ -0,0 +1,462 @@
+//! Compute passes run before the image is drawn each frame. They share a
+//! storage buffer (`iData`, an array of `vec4` kept between frames) and a
+//! storage image (`iComputeImage`), which the image pass reads back as `iData`
+//! and `iComputeTexture`. All of them live in bind group 1.
+
+use crate::app::with_error_scope;
+use eframe::wgpu;
+use std::borrow::Cow;
+
+/// Format of `iComputeImage`. It has to be filterable, because the image pass
+/// samples it.
+pub const COMPUTE_IMAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// One compute shader, dispatched with `workgroups` groups.
+#[derive(Clone)]
+pub struct ComputePass {
+    pub content: String,
+    pub workgroups: [u32; 3],
+    pub enabled: bool,
+}
+
+impl Default for ComputePass {
+    fn default() -> Self {
+        Self {
+            content: include_str!("default_compute.glsl").to_owned(),
+            // Covers the default image with the default 8x8 workgroup size.
+            workgroups: [64, 64, 1],
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ComputeSettings {
+    /// Run in order, before the image pass.
+    pub passes: Vec<ComputePass>,
+    /// Number of `vec4`s in `iData`.
+    pub data_len: u32,
+    pub image_size: [u32; 2],
+}
+
+impl Default for ComputeSettings {
+    fn default() -> Self {
+        Self {
+            passes: Vec::new(),
+            data_len: 1 << 16,
+            image_size: [512, 512],
+        }
+    }
+}
+
+impl ComputeSettings {
+    /// Whether any pass runs, in which case the image pass gets the storage
+    /// bindings too.
+    pub fn is_active(&self) -> bool {
+        self.passes.iter().any(|pass| pass.enabled)
+    }
+}
+
+fn uniform_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("compute_uniform_layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+/// Group 1 as the compute passes see it. `iData` is read-write. WebGPU only
+/// allows read-write storage textures in the r32 formats, so
+/// `iComputeImage` is write-only; passes that need earlier values keep them
+/// in `iData`.
+fn compute_storage_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: COMPUTE_IMAGE_FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        },
+    ]
+}
+
+fn compute_storage_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("compute_storage_layout"),
+        entries: &compute_storage_entries(),
+    })
+}
+
+/// Group 1 as the image pass sees it: the buffer read-only and the image as a
+/// sampled texture, visible to `stages`.
+fn render_storage_entries(stages: wgpu::ShaderStages) -> [wgpu::BindGroupLayoutEntry; 3] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: stages,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: stages,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: stages,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        },
+    ]
+}
+
+/// `stages` are the image pass stages that read the storage, see
+/// [`crate::app::RenderMode::compute_stages`].
+pub fn render_storage_layout(
+    device: &wgpu::Device,
+    stages: wgpu::ShaderStages,
+) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("render_storage_layout"),
+        entries: &render_storage_entries(stages),
+    })
+}
+
+pub fn create_compute_pipeline(
+    device: &wgpu::Device,
+    spirv: Cow<'_, [u32]>,
+) -> crate::app::Result<wgpu::ComputePipeline> {
+    crate::app::validate_spirv(&spirv).map_err(|err| anyhow::anyhow!("Compute shader: {}", err))?;
+    let module = with_error_scope(device, "compute shader module", || {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("compute_shader"),
+            source: wgpu::ShaderSource::SpirV(spirv),
+        })
+    })?;
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("compute_pipeline_layout"),
+        bind_group_layouts: &[&uniform_layout(device), &compute_storage_layout(device)],
+        push_constant_ranges: &[],
+    });
+    with_error_scope(device, "compute pipeline", || {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("compute_pipeline"),
+            layout: Some(&layout),
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        })
+    })
+}
+
+/// Largest `iData` length, in `vec4`s, that `device` can bind as one storage
+/// buffer.
+pub fn max_data_len(device: &wgpu::Device) -> u32 {
+    let limits = device.limits();
+    let max_bytes = u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size);
+    (max_bytes / 16).min(u64::from(u32::MAX)) as u32
+}
+
+/// The storage shared by the compute passes and the image pass.
+pub struct ComputeResources {
+    data_len: u32,
+    image_size: [u32; 2],
+    render_stages: wgpu::ShaderStages,
+    uniform_bind_group: wgpu::BindGroup,
+    storage_bind_group: wgpu::BindGroup,
+    /// `iComputeImage`.
+    pub image: wgpu::Texture,
+    /// Bound as group 1 when drawing the image.
+    pub render_bind_group: wgpu::BindGroup,
+}
+
+impl ComputeResources {
+    /// Allocates zeroed storage for `settings`, readable by the image pass
+    /// stages `render_stages`. `iData` is capped at [`max_data_len`].
+    pub fn new(
+        device: &wgpu::Device,
+        uniform_buffer: &wgpu::Buffer,
+        settings: &ComputeSettings,
+        render_stages: wgpu::ShaderStages,
+    ) -> crate::app::Result<Self> {
+        with_error_scope(device, "the compute storage", || {
+            Self::new_unchecked(device, uniform_buffer, settings, render_stages)
+        })
+    }
+
+    fn new_unchecked(
+        device: &wgpu::Device,
+        uniform_buffer: &wgpu::Buffer,
+        settings: &ComputeSettings,
+        render_stages: wgpu::ShaderStages,
+    ) -> Self {
+        let data_len = settings.data_len.clamp(1, max_data_len(device));
+        let data_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("compute_data"),
+            size: u64::from(data_len) * 16,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let image = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("compute_image"),
+            size: wgpu::Extent3d {
+                width: settings.image_size[0].max(1),
+                height: settings.image_size[1].max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: COMPUTE_IMAGE_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let image_view = image.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("compute_image_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("compute_uniform_bind_group"),
+            layout: &uniform_layout(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let storage_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("compute_storage_bind_group"),
+            layout: &compute_storage_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: data_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&image_view),
+                },
+            ],
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("render_storage_bind_group"),
+            layout: &render_storage_layout(device, render_stages),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: data_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&image_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        Self {
+            data_len: settings.data_len,
+            image_size: settings.image_size,
+            render_stages,
+            uniform_bind_group,
+            storage_bind_group,
+            image,
+            render_bind_group,
+        }
+    }
+
+    /// Whether the storage was allocated for the sizes in `settings` and
+    /// the image pass stages `render_stages`.
+    pub fn fits(&self, settings: &ComputeSettings, render_stages: wgpu::ShaderStages) -> bool {
+        self.data_len == settings.data_len
+            && self.image_size == settings.image_size
+            && self.render_stages == render_stages
+    }
+
+    /// Records one dispatch per pipeline, in order.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipelines: &[(wgpu::ComputePipeline, [u32; 3])],
+    ) {
+        if pipelines.is_empty() {
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("compute_pass"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_bind_group(1, &self.storage_bind_group, &[]);
+        for (pipeline, [x, y, z]) in pipelines {
+            pass.set_pipeline(pipeline);
+            pass.dispatch_workgroups(*x, *y, *z);
+        }
+    }
+}
+
+/// Declarations that give the image pass read access to the compute storage,
+/// substituted for `{compute}` in the templates.
+pub fn compute_declarations(language: crate::app::ShaderLanguage) -> &'static str {
+    match language {
+        crate::app::ShaderLanguage::Glsl => {
+            "layout (set = 1, binding = 0, std430) readonly buffer ComputeData { vec4 iData[]; };\n\
+             layout (set = 1, binding = 1) uniform texture2D _iComputeTexture;\n\
+             layout (set = 1, binding = 2) uniform sampler _iComputeSampler;\n\
+             #define iComputeTexture sampler2D(_iComputeTexture, _iComputeSampler)\n"
+        }
+        crate::app::ShaderLanguage::Wgsl => {
+            "@group(1) @binding(0) var<storage, read> iData: array<vec4<f32>>;\n\
+             @group(1) @binding(1) var iComputeTexture: texture_2d<f32>;\n\
+             @group(1) @binding(2) var iComputeSampler: sampler;\n"
+        }
+        crate::app::ShaderLanguage::Hlsl => {
+            "StructuredBuffer<float4> iData : register(t0, space1);\n\
+             Texture2D iComputeTexture : register(t1, space1);\n\
+             SamplerState iComputeSampler : register(s2, space1);\n"
+        }
+    }
+}
+
+/// Checks that every group 1 resource `module` declares has a matching entry
+/// in `entries`.
+#[cfg(test)]
+fn assert_bindings_match(module: &naga::Module, entries: &[wgpu::BindGroupLayoutEntry]) {
+    for (_, variable) in module.global_variables.iter() {
+        let Some(binding) = variable
+            .binding
+            .as_ref()
+            .filter(|binding| binding.group == 1)
+        else {
+            continue;
+        };
+        let entry = entries
+            .iter()
+            .find(|entry| entry.binding == binding.binding)
+            .unwrap_or_else(|| panic!("no layout entry for {:?}", variable.name));
+        let matches = match (&module.types[variable.ty].inner, entry.ty) {
+            (
+                naga::TypeInner::Array { .. },
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    ..
+                },
+            )
+            | (
+                naga::TypeInner::Struct { .. },
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    ..
+                },
+            ) => match variable.space {
+                naga::AddressSpace::Storage { access } => {
+                    read_only != access.contains(naga::StorageAccess::STORE)
+                }
+                _ => false,
+            },
+            (
+                naga::TypeInner::Image {
+                    class: naga::ImageClass::Storage { format, access },
+                    ..
+                },
+                wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: wgpu::TextureFormat::Rgba16Float,
+                    ..
+                },
+            ) => {
+                *format == naga::StorageFormat::Rgba16Float && *access == naga::StorageAccess::STORE
+            }
+            (
+                naga::TypeInner::Image {
+                    class: naga::ImageClass::Sampled { .. },
+                    ..
+                },
+                wgpu::BindingType::Texture { .. },
+            ) => true,
+            (naga::TypeInner::Sampler { comparison: false }, wgpu::BindingType::Sampler(_)) => true,
+            _ => false,
+        };
+        assert!(
+            matches,
+            "{:?} does not match its layout entry {:?}",
+            variable.name, entry
+        );
+    }
+}
+
+#[test]
+fn storage_declarations_match_the_layouts() {
+    use crate::app::{CompileSettings, ShaderCache, ShaderLanguage};
+
+    let settings = CompileSettings {
+        compute: true,
+        ..CompileSettings::default()
+    };
+    let mut cache = ShaderCache::in_memory();
+    for language in ShaderLanguage::ALL {
+        let shader = crate::app::load_fragment_shader(
+            language.default_content(),
+            language,
+            &settings,
+            &mut cache,
+            &mut Vec::new(),
+        )
+        .unwrap_or_else(|err| panic!("{}: {:#}", language.name(), err));
+        let validated = shader
+            .validate()
+            .unwrap_or_else(|err| panic!("{}: {:#}", language.name(), err));
+        assert_bindings_match(
+            &validated.module,
+            &render_storage_entries(wgpu::ShaderStages::FRAGMENT),
+        );
+    }
+    let compute = crate::app::load_compute_shader(
+        include_str!("default_compute.glsl"),
+        &settings,
+        &mut cache,
+        &mut Vec::new(),
+    )
+    .unwrap();
+    let validated = crate::app::validate_spirv(&compute).unwrap();
+    assert_bindings_match(&validated.module, &compute_storage_entries());
+}
@@ -0,0 +1,15 @@
+// Runs once per invocation. iData (vec4s kept between frames) and
+// iComputeImage are read by the image pass as iData and iComputeTexture.
+layout (local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(iComputeImage);
+    if (pixel.x >= size.x || pixel.y >= size.y) {
+        return;
+    }
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(size);
+    vec2 center = 0.5 + 0.25 * vec2(cos(iTime), sin(iTime));
+    float disc = smoothstep(0.1, 0.09, length(uv - center));
+    imageStore(iComputeImage, pixel, vec4(vec3(disc), 1.0));
+}
@@ -0,0 +1,14 @@
+// vertexId runs from 0 to vertexCount - 1. Write gl_Position and v_color.
+void main() {
+    float across = floor(sqrt(vertexCount));
+    float x = mod(vertexId, across);
+    float y = floor(vertexId / across);
+    vec2 uv = vec2(x, y) / max(across - 1.0, 1.0);
+
+    float wave = sin(uv.x * 6.2831 * 2.0 + iTime) * sin(uv.y * 6.2831 + iTime * 0.7);
+    vec2 position = uv * 1.8 - 0.9;
+    position.y += wave * 0.05;
+    gl_Position = vec4(position, 0.0, 1.0);
+
+    v_color = vec4(0.5 + 0.5 * cos(iTime + uv.xyx * 3.0 + vec3(0.0, 2.0, 4.0)), 1.0);
+}
@@ -0,0 +1,147 @@
+use log::{error, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// One message from compiling or validating a shader.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// 1-based line in the shader being edited, when the compiler reports one.
+    pub line: Option<u32>,
+    /// 1-based column, which only naga reports.
+    pub column: Option<u32>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+
+    /// Splits an error into diagnostics, keeping the individual messages and
+    /// their severities when it comes from shaderc, and the location when it
+    /// comes from naga's WGSL front-end.
+    pub fn from_error(err: &anyhow::Error) -> Vec<Self> {
+        if let Some(err) = err.downcast_ref::<SourceError>() {
+            return vec![Self {
+                line: Some(err.line),
+                column: Some(err.column),
+                ..Self::error(err.to_string())
+            }];
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(shaderc::Error::CompilationError(_, output)) = err.downcast_ref() {
+            let diagnostics = parse_compiler_output(output);
+            if !diagnostics.is_empty() {
+                return diagnostics;
+            }
+        }
+        vec![Self::error(format!("{:#}", err))]
+    }
+
+    /// Sends the diagnostic to the log at the matching level, so it can be
+    /// filtered there.
+    pub fn log(&self) {
+        match self.severity {
+            Severity::Warning => warn!("{}", self.message),
+            Severity::Error => error!("{}", self.message),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity.name(), self.message)
+    }
+}
+
+/// An error located in the shader being edited, e.g. a WGSL parse error.
+#[derive(Debug)]
+pub struct SourceError {
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for SourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+/// Parses shaderc's `file:line: severity: message` output. Lines without a
+/// severity continue the previous message, and the trailing summary
+/// ("1 error and 2 warnings generated.") is dropped.
+pub fn parse_compiler_output(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        let (severity, location) = if let Some((location, _)) = line.split_once(": error:") {
+            (Some(Severity::Error), location)
+        } else if let Some((location, _)) = line.split_once(": warning:") {
+            (Some(Severity::Warning), location)
+        } else {
+            (None, "")
+        };
+        match (severity, diagnostics.last_mut()) {
+            (Some(severity), _) => diagnostics.push(Diagnostic {
+                severity,
+                message: line.to_owned(),
+                line: location
+                    .rsplit_once(':')
+                    .and_then(|(_, line)| line.parse().ok()),
+                column: None,
+            }),
+            (None, _) if line.ends_with(" generated.") => {}
+            (None, Some(last)) => {
+                last.message.push('\n');
+                last.message.push_str(line);
+            }
+            (None, None) => diagnostics.push(Diagnostic::error(line)),
+        }
+    }
+    diagnostics
+}
+
+#[test]
+fn parse_shaderc_output() {
+    let output = "shader.glsl:3: warning: '#extension' : extension not supported\n\
+                  shader.glsl:7: error: 'foo' : undeclared identifier\n\
+                  \x20 note: declared here\n\
+                  1 error and 1 warning generated.\n";
+    let diagnostics = parse_compiler_output(output);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].severity, Severity::Warning);
+    assert_eq!(diagnostics[1].severity, Severity::Error);
+    assert_eq!(diagnostics[1].line, Some(7));
+    assert!(diagnostics[1].message.ends_with("note: declared here"));
+}
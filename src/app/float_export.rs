@@ -0,0 +1,291 @@
+//! Export of unclamped float images to OpenEXR and Radiance HDR, for
+//! compositing and look development outside the app.
+
+use crate::app::{FloatImage, Result};
+use anyhow::{anyhow, bail, Context as _};
+use eframe::wgpu;
+use std::io::Write;
+use std::path::Path;
+
+/// Image a float export reads back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExportPass {
+    /// The image pass's offscreen target, before exposure and tone mapping.
+    #[default]
+    Image,
+    /// The tone-mapped output, as it is shown.
+    Output,
+    /// The compute passes' `iComputeImage`.
+    ComputeImage,
+}
+
+impl ExportPass {
+    pub const ALL: [Self; 3] = [Self::Image, Self::Output, Self::ComputeImage];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Output => "output",
+            Self::ComputeImage => "compute",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// OpenEXR with 32-bit float RGBA channels.
+    Exr,
+    /// Radiance RGBE. It has no alpha channel, so alpha is dropped.
+    Hdr,
+}
+
+impl FloatFormat {
+    pub const ALL: [Self; 2] = [Self::Exr, Self::Hdr];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Exr => "OpenEXR",
+            Self::Hdr => "Radiance HDR",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Exr => "exr",
+            Self::Hdr => "hdr",
+        }
+    }
+
+    /// The format `path`'s extension names, if it is a float format.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension() == extension)
+    }
+}
+
+impl FloatImage {
+    /// Writes an EXR or HDR file, chosen by `path`'s extension.
+    pub fn write_float(&self, path: &Path) -> Result<()> {
+        let format = FloatFormat::from_path(path)
+            .ok_or_else(|| anyhow!("{} is not an .exr or .hdr file", path.display()))?;
+        match format {
+            FloatFormat::Exr => self.write_exr(path),
+            FloatFormat::Hdr => {
+                let file = std::fs::File::create(path)
+                    .with_context(|| format!("Failed to create {}", path.display()))?;
+                let mut writer = std::io::BufWriter::new(file);
+                self.write_hdr(&mut writer)?;
+                writer.flush()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes all four channels as 32-bit floats.
+    pub fn write_exr(&self, path: &Path) -> Result<()> {
+        let width = self.width as usize;
+        exr::prelude::write_rgba_file(path, width, self.height as usize, |x, y| {
+            let offset = (y * width + x) * 4;
+            let pixel = &self.pixels[offset..offset + 4];
+            (pixel[0], pixel[1], pixel[2], pixel[3])
+        })
+        .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Writes a Radiance RGBE image with run-length encoded scanlines.
+    /// Negative values become 0 and alpha is dropped.
+    pub fn write_hdr(&self, writer: &mut impl Write) -> Result<()> {
+        write!(
+            writer,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.height, self.width
+        )?;
+        let width = self.width as usize;
+        // Scanlines outside this range cannot be run-length encoded.
+        let encode = (8..0x8000).contains(&width);
+        let mut line = Vec::new();
+        for row in self.pixels.chunks_exact(width * 4) {
+            let rgbe: Vec<[u8; 4]> = row
+                .chunks_exact(4)
+                .map(|pixel| rgbe([pixel[0], pixel[1], pixel[2]]))
+                .collect();
+            line.clear();
+            if encode {
+                line.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+                for channel in 0..4 {
+                    let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[channel]).collect();
+                    run_length_encode(&values, &mut line);
+                }
+            } else {
+                line.extend(rgbe.iter().flatten());
+            }
+            writer.write_all(&line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shared-exponent encoding of a color: three 8-bit mantissas and a biased
+/// exponent.
+fn rgbe(rgb: [f32; 3]) -> [u8; 4] {
+    let rgb = rgb.map(|value| if value.is_nan() { 0.0 } else { value.max(0.0) });
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    if max < 1e-32 {
+        return [0; 4];
+    }
+    // `max` is scaled into 128..256.
+    let exponent = (max.log2().floor() as i32 + 1).clamp(-127, 127);
+    let scale = 256.0 * 2f32.powi(-exponent);
+    let [r, g, b] = rgb.map(|value| (value * scale).min(255.0) as u8);
+    [r, g, b, (exponent + 128) as u8]
+}
+
+/// Appends Radiance's run-length encoding of one channel of a scanline: runs
+/// of a repeated byte as `128 + length, byte`, everything else as `length,
+/// bytes`, both at most 127 and 128 bytes long.
+fn run_length_encode(values: &[u8], out: &mut Vec<u8>) {
+    let run_at = |index: usize| {
+        values[index..]
+            .iter()
+            .take(127)
+            .take_while(|&&value| value == values[index])
+            .count()
+    };
+    let mut index = 0;
+    while index < values.len() {
+        let run = run_at(index);
+        if run >= 3 {
+            out.extend_from_slice(&[128 + run as u8, values[index]]);
+            index += run;
+            continue;
+        }
+        let start = index;
+        while index < values.len() && index - start < 128 && (index == start || run_at(index) < 3) {
+            index += 1;
+        }
+        out.push((index - start) as u8);
+        out.extend_from_slice(&values[start..index]);
+    }
+}
+
+/// Copies `texture` after the commands in `encoder`, waits for the GPU and
+/// converts the texels to 32-bit floats. Only RGBA float formats are
+/// supported.
+pub fn read_float_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> Result<FloatImage> {
+    let format = texture.format();
+    if !matches!(
+        format,
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float
+    ) {
+        bail!("Cannot read back {:?} textures as floats", format);
+    }
+    let size = texture.size();
+    let texel_bytes = format
+        .block_copy_size(None)
+        .context("The texture cannot be copied")?;
+    // Rows of a texture copy must be aligned to 256 bytes.
+    let row_bytes = size.width * texel_bytes;
+    let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback_buffer"),
+        size: u64::from(padded_row_bytes) * u64::from(size.height),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .context("The readback buffer was dropped")?
+        .context("Failed to read back the frame")?;
+
+    let mapped = slice.get_mapped_range();
+    let mut pixels = Vec::with_capacity((size.width * size.height * 4) as usize);
+    for row in mapped.chunks_exact(padded_row_bytes as usize) {
+        pixels.extend(decode_floats(format, &row[..row_bytes as usize]));
+    }
+    drop(mapped);
+    buffer.unmap();
+    Ok(FloatImage {
+        width: size.width,
+        height: size.height,
+        pixels,
+    })
+}
+
+/// Little-endian half or single floats, as `format` stores them.
+fn decode_floats(format: wgpu::TextureFormat, bytes: &[u8]) -> Vec<f32> {
+    if format == wgpu::TextureFormat::Rgba16Float {
+        bytes
+            .chunks_exact(2)
+            .map(|bytes| half::f16::from_le_bytes([bytes[0], bytes[1]]).to_f32())
+            .collect()
+    } else {
+        bytes
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    }
+}
+
+#[test]
+fn rgbe_encoding() {
+    assert_eq!(rgbe([0.0, 0.0, 0.0]), [0; 4]);
+    assert_eq!(rgbe([1.0, 0.5, 0.0]), [128, 64, 0, 129]);
+    assert_eq!(rgbe([-1.0, f32::NAN, 4.0]), [0, 0, 128, 131]);
+}
+
+#[test]
+fn hdr_scanlines_are_run_length_encoded() {
+    let mut out = Vec::new();
+    run_length_encode(&[7, 7, 7, 7, 1, 2, 3, 3], &mut out);
+    assert_eq!(out, [132, 7, 4, 1, 2, 3, 3]);
+
+    let image = FloatImage {
+        width: 8,
+        height: 1,
+        pixels: [1.0, 1.0, 1.0, 0.5].repeat(8),
+    };
+    let mut file = Vec::new();
+    image.write_hdr(&mut file).unwrap();
+    let header = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n";
+    assert!(file.starts_with(header.as_bytes()));
+    assert_eq!(
+        &file[header.len()..],
+        [2, 2, 0, 8, 136, 128, 136, 128, 136, 128, 136, 129]
+    );
+}
+
+#[test]
+fn half_floats_are_decoded() {
+    let bytes = [0x00, 0x3c, 0x00, 0xc0];
+    assert_eq!(
+        decode_floats(wgpu::TextureFormat::Rgba16Float, &bytes),
+        [1.0, -2.0]
+    );
+}
@@ -0,0 +1,104 @@
+/// Corner of the image where `fragCoord` is `(0, 0)`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum FragCoordOrigin {
+    /// Shadertoy's (and OpenGL's) convention.
+    #[default]
+    BottomLeft,
+    /// wgpu's, Direct3D's and Metal's convention.
+    TopLeft,
+}
+
+impl FragCoordOrigin {
+    pub const ALL: [Self; 2] = [Self::BottomLeft, Self::TopLeft];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::BottomLeft => "Bottom-left (Shadertoy)",
+            Self::TopLeft => "Top-left",
+        }
+    }
+}
+
+/// How `gl_FragCoord` is turned into the `fragCoord` passed to `mainImage`.
+/// The default matches Shadertoy: bottom-left origin, pixel centers at +0.5.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct FragCoordConvention {
+    pub origin: FragCoordOrigin,
+    /// Put pixel centers on whole numbers instead of at +0.5.
+    pub integer_pixel_centers: bool,
+}
+
+impl FragCoordConvention {
+    /// Scale (`xy`) and offset (`zw`) the templates apply to `gl_FragCoord.xy`.
+    ///
+    /// `gl_FragCoord` is relative to the whole render target with a top-left
+    /// origin, while the shader is drawn into a viewport somewhere inside it,
+    /// so `viewport_min` is subtracted first. `viewport_size` is in pixels,
+    /// the same as `iResolution`.
+    pub fn transform(self, viewport_min: [f32; 2], viewport_size: [f32; 2]) -> [f32; 4] {
+        let center = if self.integer_pixel_centers { 0.5 } else { 0.0 };
+        match self.origin {
+            FragCoordOrigin::TopLeft => [
+                1.0,
+                1.0,
+                -viewport_min[0] - center,
+                -viewport_min[1] - center,
+            ],
+            FragCoordOrigin::BottomLeft => [
+                1.0,
+                -1.0,
+                -viewport_min[0] - center,
+                viewport_min[1] + viewport_size[1] - center,
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+fn apply(transform: [f32; 4], frag_coord: [f32; 2]) -> [f32; 2] {
+    [
+        frag_coord[0] * transform[0] + transform[2],
+        frag_coord[1] * transform[1] + transform[3],
+    ]
+}
+
+#[test]
+fn shadertoy_orientation() {
+    // A 4x2 viewport drawn 10 pixels right of and 20 below the target's corner.
+    let transform = FragCoordConvention::default().transform([10.0, 20.0], [4.0, 2.0]);
+    // Top-left pixel of the viewport.
+    assert_eq!(apply(transform, [10.5, 20.5]), [0.5, 1.5]);
+    // Bottom-left pixel is Shadertoy's origin.
+    assert_eq!(apply(transform, [10.5, 21.5]), [0.5, 0.5]);
+    // Top-right pixel.
+    assert_eq!(apply(transform, [13.5, 20.5]), [3.5, 1.5]);
+}
+
+#[test]
+fn alternative_conventions() {
+    let top_left = FragCoordConvention {
+        origin: FragCoordOrigin::TopLeft,
+        integer_pixel_centers: false,
+    };
+    let transform = top_left.transform([10.0, 20.0], [4.0, 2.0]);
+    assert_eq!(apply(transform, [10.5, 20.5]), [0.5, 0.5]);
+    assert_eq!(apply(transform, [13.5, 21.5]), [3.5, 1.5]);
+
+    let integer_centers = FragCoordConvention {
+        origin: FragCoordOrigin::BottomLeft,
+        integer_pixel_centers: true,
+    };
+    let transform = integer_centers.transform([0.0, 0.0], [4.0, 2.0]);
+    assert_eq!(apply(transform, [0.5, 1.5]), [0.0, 0.0]);
+    assert_eq!(apply(transform, [3.5, 0.5]), [3.0, 1.0]);
+}
+
+#[test]
+fn tiles_see_the_whole_image() {
+    // The bottom-right 2x1 tile of a 4x2 image is rendered into its own
+    // target, with the image's viewport starting before the target's corner.
+    let transform = FragCoordConvention::default().transform([-2.0, -1.0], [4.0, 2.0]);
+    assert_eq!(apply(transform, [0.5, 0.5]), [2.5, 0.5]);
+    assert_eq!(apply(transform, [1.5, 0.5]), [3.5, 0.5]);
+}
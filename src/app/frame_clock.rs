@@ -0,0 +1,44 @@
+/// Fixed-rate timing for exports. Unlike live playback, which reads the wall
+/// clock, every frame's `iTime` is derived from its index, so a frame renders
+/// the same regardless of how long the previous ones took and no rounding
+/// error accumulates over long sequences.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameClock {
+    pub fps: f64,
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self { fps: 60.0 }
+    }
+}
+
+impl FrameClock {
+    pub fn new(fps: f64) -> Self {
+        Self { fps }
+    }
+
+    /// `iTime` of `frame`; frame 0 starts at 0 seconds.
+    pub fn time(&self, frame: u32) -> f32 {
+        (f64::from(frame) / self.fps) as f32
+    }
+
+    /// `iTimeDelta` of every frame.
+    pub fn time_delta(&self) -> f32 {
+        (1.0 / self.fps) as f32
+    }
+
+    /// The frame shown at `time` seconds.
+    pub fn frame_at(&self, time: f32) -> u32 {
+        (f64::from(time) * self.fps).round().max(0.0) as u32
+    }
+}
+
+#[test]
+fn frame_times_do_not_drift() {
+    let clock = FrameClock::new(30.0);
+    assert_eq!(clock.time(0), 0.0);
+    assert_eq!(clock.time(90), 3.0);
+    assert_eq!(clock.time(30 * 60 * 60), 3600.0);
+    assert_eq!(clock.frame_at(clock.time(12345)), 12345);
+}
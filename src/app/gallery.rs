@@ -0,0 +1,394 @@
+//! Static HTML gallery of a tree of shaders: a thumbnail of each at a fixed
+//! time, its title and whether it compiles.
+
+use crate::app::{
+    frame_uniform, CompileSettings, ComputeSettings, Diagnostic, FrameClock, HeadlessRenderer,
+    OutputSettings, Result, Severity, ShaderLanguage,
+};
+use anyhow::Context as _;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct GalleryOptions {
+    pub size: [u32; 2],
+    /// Value of `iTime` the thumbnails are rendered at.
+    pub time: f32,
+}
+
+impl Default for GalleryOptions {
+    fn default() -> Self {
+        Self {
+            size: [320, 180],
+            time: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GalleryStatus {
+    /// Compiled and rendered, with this many warnings.
+    Rendered {
+        warnings: usize,
+    },
+    Failed(String),
+}
+
+/// One shader of the gallery.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GalleryEntry {
+    /// Relative to the gallery's root.
+    pub path: PathBuf,
+    pub title: String,
+    /// Relative to the output directory; set when the shader rendered.
+    pub thumbnail: Option<PathBuf>,
+    pub status: GalleryStatus,
+}
+
+/// Shader files anywhere under `dir`, sorted by path. Hidden directories,
+/// `golden` reference directories and symlinked directories are skipped.
+pub fn find_shaders(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut shaders = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in
+            std::fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let path = entry.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            // Symlinked directories are not followed, so a link cycle cannot
+            // make the walk loop forever.
+            if entry.file_type()?.is_dir() {
+                if !name.starts_with('.') && name != "golden" {
+                    dirs.push(path);
+                }
+            } else if ShaderLanguage::from_path(&path).is_some() {
+                shaders.push(path);
+            }
+        }
+    }
+    shaders.sort();
+    Ok(shaders)
+}
+
+/// The first line of the comments a shader starts with, e.g. "Sea" from
+/// `// Sea\n// by someone`.
+pub fn shader_title(content: &str) -> Option<String> {
+    let mut in_block = false;
+    for line in content.lines() {
+        let mut line = line.trim();
+        if !in_block {
+            if let Some(comment) = line.strip_prefix("//") {
+                line = comment;
+            } else if let Some(comment) = line.strip_prefix("/*") {
+                in_block = true;
+                line = comment;
+            } else if line.is_empty() {
+                continue;
+            } else {
+                return None;
+            }
+        }
+        if in_block {
+            if let Some(end) = line.find("*/") {
+                in_block = false;
+                line = &line[..end];
+            }
+        }
+        let title = line.trim().trim_start_matches(['*', '/', '-', '=']).trim();
+        if !title.is_empty() {
+            return Some(title.to_owned());
+        }
+    }
+    None
+}
+
+/// Thumbnail file of the shader at `path`, relative to the gallery root. The
+/// thumbnails mirror the shader tree, so distinct shaders never share one.
+fn thumbnail_name(path: &Path) -> PathBuf {
+    let mut name = Path::new("thumbnails").join(path).into_os_string();
+    name.push(".png");
+    PathBuf::from(name)
+}
+
+/// Renders a thumbnail of every shader under `dir` into `out_dir`. Shaders
+/// that fail to compile or render are recorded, not returned as errors.
+/// `progress` is called after each shader.
+pub fn build_gallery(
+    renderer: &mut HeadlessRenderer,
+    dir: &Path,
+    out_dir: &Path,
+    options: &GalleryOptions,
+    mut progress: impl FnMut(&GalleryEntry),
+) -> Result<Vec<GalleryEntry>> {
+    std::fs::create_dir_all(out_dir.join("thumbnails"))
+        .with_context(|| format!("Failed to create {}", out_dir.display()))?;
+    let clock = FrameClock::default();
+    let frame = clock.frame_at(options.time);
+    let mut entries = Vec::new();
+    for shader in find_shaders(dir)? {
+        let path = shader.strip_prefix(dir).unwrap_or(&shader).to_owned();
+        let content = std::fs::read_to_string(&shader)
+            .with_context(|| format!("Failed to read {}", shader.display()));
+        let title = content
+            .as_deref()
+            .ok()
+            .and_then(shader_title)
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            });
+        let thumbnail = thumbnail_name(&path);
+        let result = content.and_then(|content| {
+            render_thumbnail(
+                renderer,
+                &content,
+                &shader,
+                &out_dir.join(&thumbnail),
+                options,
+                frame,
+            )
+        });
+        let status = match result {
+            Ok(warnings) => GalleryStatus::Rendered { warnings },
+            Err(err) => GalleryStatus::Failed(format!("{:#}", err)),
+        };
+        let entry = GalleryEntry {
+            path,
+            title,
+            thumbnail: matches!(status, GalleryStatus::Rendered { .. }).then_some(thumbnail),
+            status,
+        };
+        progress(&entry);
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Returns the number of compiler warnings.
+fn render_thumbnail(
+    renderer: &mut HeadlessRenderer,
+    content: &str,
+    shader: &Path,
+    thumbnail: &Path,
+    options: &GalleryOptions,
+    frame: u32,
+) -> Result<usize> {
+    let language = ShaderLanguage::from_path(shader).context("Unknown shader language")?;
+    let mut diagnostics = Vec::new();
+    if let Err(err) = renderer.set_shaders(
+        content,
+        language,
+        &CompileSettings::default(),
+        &ComputeSettings::default(),
+        OutputSettings::default(),
+        &mut diagnostics,
+    ) {
+        let messages: Vec<String> = Diagnostic::from_error(&err)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        anyhow::bail!("{}", messages.join("\n"));
+    }
+    let clock = FrameClock::default();
+    let image = renderer.render(&frame_uniform(
+        options.size,
+        options.time,
+        clock.time_delta(),
+        frame,
+    ))?;
+    if let Some(dir) = thumbnail.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    image.write_png(thumbnail)?;
+    Ok(diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        .count())
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Percent-encodes everything but URL-safe characters, so a path segment can
+/// go in a link whatever the file is called.
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(char::from(byte));
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+/// A self-contained index page for `entries`, with thumbnail links relative
+/// to the page.
+pub fn gallery_html(title: &str, entries: &[GalleryEntry], options: &GalleryOptions) -> String {
+    let failed = entries
+        .iter()
+        .filter(|entry| matches!(entry.status, GalleryStatus::Failed(_)))
+        .count();
+    let [width, height] = options.size;
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n<style>\n\
+         body {{ font-family: sans-serif; background: #1b1b1b; color: #ddd; margin: 2em; }}\n\
+         .grid {{ display: grid; grid-template-columns: repeat(auto-fill, minmax({width}px, 1fr)); gap: 1.5em; }}\n\
+         .card {{ background: #262626; border-radius: 6px; overflow: hidden; }}\n\
+         .card img, .card .missing {{ display: block; width: 100%; aspect-ratio: {width} / {height}; background: #000; }}\n\
+         .card .info {{ padding: 0.6em 0.8em; }}\n\
+         .card h2 {{ font-size: 1em; margin: 0 0 0.3em; }}\n\
+         .path {{ font-family: monospace; font-size: 0.85em; color: #999; }}\n\
+         .status {{ font-size: 0.85em; margin-top: 0.3em; }}\n\
+         .ok {{ color: #7c7; }} .warning {{ color: #db6; }} .error {{ color: #e66; }}\n\
+         pre {{ white-space: pre-wrap; font-size: 0.75em; color: #e99; max-height: 12em; overflow: auto; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n\
+         <p>{count} shaders, {failed} failing, rendered at {width}x{height}, iTime = {time}.</p>\n\
+         <div class=\"grid\">\n",
+        title = escape_html(title),
+        count = entries.len(),
+        failed = failed,
+        width = width,
+        height = height,
+        time = options.time,
+    );
+    for entry in entries {
+        let path = escape_html(&entry.path.to_string_lossy());
+        html.push_str("<div class=\"card\">\n");
+        match &entry.thumbnail {
+            Some(thumbnail) => {
+                // Links use forward slashes on every platform.
+                let src: Vec<String> = thumbnail
+                    .components()
+                    .map(|component| percent_encode(&component.as_os_str().to_string_lossy()))
+                    .collect();
+                let _ = writeln!(
+                    html,
+                    "<img src=\"{}\" alt=\"{}\" loading=\"lazy\">",
+                    escape_html(&src.join("/")),
+                    path
+                );
+            }
+            None => html.push_str("<div class=\"missing\"></div>\n"),
+        }
+        let _ = write!(
+            html,
+            "<div class=\"info\">\n<h2>{}</h2>\n<div class=\"path\">{}</div>\n",
+            escape_html(&entry.title),
+            path
+        );
+        match &entry.status {
+            GalleryStatus::Rendered { warnings: 0 } => {
+                html.push_str("<div class=\"status ok\">compiles</div>\n");
+            }
+            GalleryStatus::Rendered { warnings } => {
+                let _ = writeln!(
+                    html,
+                    "<div class=\"status warning\">compiles with {} warning{}</div>",
+                    warnings,
+                    if *warnings == 1 { "" } else { "s" }
+                );
+            }
+            GalleryStatus::Failed(message) => {
+                let _ = writeln!(
+                    html,
+                    "<div class=\"status error\">fails</div>\n<pre>{}</pre>",
+                    escape_html(message)
+                );
+            }
+        }
+        html.push_str("</div>\n</div>\n");
+    }
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+#[test]
+fn titles_from_leading_comments() {
+    assert_eq!(
+        shader_title("\n// Sea\n// by someone\nvoid mainImage() {}"),
+        Some("Sea".to_owned())
+    );
+    assert_eq!(
+        shader_title("/*\n * Protean clouds\n */\n"),
+        Some("Protean clouds".to_owned())
+    );
+    assert_eq!(
+        shader_title("/* Inline */ float x;"),
+        Some("Inline".to_owned())
+    );
+    assert_eq!(shader_title("//////\n// Boxed\n"), Some("Boxed".to_owned()));
+    assert_eq!(shader_title("void mainImage() {}\n// Late"), None);
+}
+
+#[test]
+fn gallery_page() {
+    let entries = [
+        GalleryEntry {
+            path: PathBuf::from("2d/waves #1.glsl"),
+            title: "Waves & <foam>".to_owned(),
+            thumbnail: Some(thumbnail_name(Path::new("2d/waves #1.glsl"))),
+            status: GalleryStatus::Rendered { warnings: 1 },
+        },
+        GalleryEntry {
+            path: PathBuf::from("broken.wgsl"),
+            title: "broken".to_owned(),
+            thumbnail: None,
+            status: GalleryStatus::Failed("error: expected `;`".to_owned()),
+        },
+    ];
+    let html = gallery_html("Shaders", &entries, &GalleryOptions::default());
+    assert!(html.contains("<img src=\"thumbnails/2d/waves%20%231.glsl.png\""));
+    assert!(html.contains("<h2>Waves &amp; &lt;foam&gt;</h2>"));
+    assert!(html.contains("compiles with 1 warning<"));
+    assert!(html.contains("<pre>error: expected `;`</pre>"));
+    assert!(html.contains("2 shaders, 1 failing"));
+}
+
+#[test]
+fn thumbnails_mirror_the_tree() {
+    assert_eq!(
+        thumbnail_name(Path::new("2d/waves.glsl")),
+        Path::new("thumbnails/2d/waves.glsl.png")
+    );
+    assert_ne!(
+        thumbnail_name(Path::new("a/b.glsl")),
+        thumbnail_name(Path::new("a_b.glsl"))
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_cycles_are_not_followed() {
+    let dir = std::env::temp_dir().join(format!("shadertoy_rs_gallery_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub/a.glsl"), "").unwrap();
+    std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+    let shaders = find_shaders(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(shaders.unwrap(), [dir.join("sub/a.glsl")]);
+}
@@ -0,0 +1,387 @@
+//! Golden-image regression tests. Every shader in a directory is rendered at
+//! fixed times and compared with reference PNGs kept next to it, in
+//! `golden/<file name>@<time>.png`, to catch template and uniform changes
+//! that alter what existing shaders draw.
+//!
+//! Pixels are compared in YIQ space with pixelmatch's weighting, so changes
+//! in brightness count more than hard-to-see changes in hue. A case fails
+//! when more pixels than allowed differ by more than the threshold; the
+//! rendered image and a diff, with the differing pixels in red over the faded
+//! reference, are then written to the diff directory.
+
+use crate::app::{
+    frame_uniform, CompileSettings, ComputeSettings, Diagnostic, FrameClock, HeadlessRenderer,
+    OutputSettings, Result, RgbaImage, ShaderLanguage,
+};
+use anyhow::{bail, Context as _};
+use std::path::{Path, PathBuf};
+
+/// Largest YIQ difference, between black and white.
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+/// How different a rendered image may be from its reference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GoldenTolerance {
+    /// Per-pixel difference in 0..1 above which a pixel counts as changed;
+    /// 0.1 ignores what is barely visible.
+    pub threshold: f32,
+    /// Fraction of the pixels that may change before the case fails.
+    pub max_mismatch: f64,
+}
+
+impl Default for GoldenTolerance {
+    fn default() -> Self {
+        Self {
+            threshold: 0.1,
+            max_mismatch: 0.001,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GoldenOptions {
+    pub size: [u32; 2],
+    /// Values of `iTime` every shader is rendered at.
+    pub times: Vec<f32>,
+    pub tolerance: GoldenTolerance,
+    /// Where the rendered images and diffs of failing cases go.
+    pub diff_dir: PathBuf,
+    /// Overwrite the references with the rendered images.
+    pub update: bool,
+    /// Write missing references instead of failing.
+    pub create_missing: bool,
+}
+
+impl Default for GoldenOptions {
+    fn default() -> Self {
+        Self {
+            size: [256, 144],
+            times: vec![0.0, 1.0],
+            tolerance: GoldenTolerance::default(),
+            diff_dir: PathBuf::from("golden-diff"),
+            update: false,
+            create_missing: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GoldenStatus {
+    /// `mismatch` is the fraction of pixels that differ.
+    Passed {
+        mismatch: f64,
+    },
+    Failed {
+        mismatch: f64,
+        diff: PathBuf,
+    },
+    Created,
+    Updated,
+    Missing,
+    Error(String),
+}
+
+impl GoldenStatus {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Self::Failed { .. } | Self::Missing | Self::Error(_))
+    }
+}
+
+impl std::fmt::Display for GoldenStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Passed { mismatch } => write!(f, "ok ({:.3}% differ)", mismatch * 100.0),
+            Self::Failed { mismatch, diff } => write!(
+                f,
+                "FAILED: {:.3}% of the pixels differ, see {}",
+                mismatch * 100.0,
+                diff.display()
+            ),
+            Self::Created => write!(f, "reference created"),
+            Self::Updated => write!(f, "reference updated"),
+            Self::Missing => write!(f, "FAILED: no reference"),
+            Self::Error(message) => write!(f, "FAILED: {}", message),
+        }
+    }
+}
+
+/// Outcome of one shader at one time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GoldenResult {
+    pub shader: PathBuf,
+    pub time: f32,
+    pub reference: PathBuf,
+    pub status: GoldenStatus,
+}
+
+impl std::fmt::Display for GoldenResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}s: {}",
+            self.shader.display(),
+            self.time,
+            self.status
+        )
+    }
+}
+
+/// Name of the images of `shader` at `time`, e.g. `rings.glsl@1.5`.
+fn case_name(shader: &Path, time: f32) -> String {
+    let file_name = shader.file_name().unwrap_or_default().to_string_lossy();
+    format!("{}@{}", file_name, time)
+}
+
+/// Reference image of `shader` at `time`.
+pub fn reference_path(shader: &Path, time: f32) -> PathBuf {
+    let dir = shader.parent().unwrap_or(Path::new(""));
+    dir.join("golden")
+        .join(format!("{}.png", case_name(shader, time)))
+}
+
+/// The shaders directly in `dir`, sorted by name.
+pub fn golden_shaders(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut shaders = Vec::new();
+    for entry in
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file() && ShaderLanguage::from_path(&path).is_some() {
+            shaders.push(path);
+        }
+    }
+    shaders.sort();
+    Ok(shaders)
+}
+
+/// Renders every shader in `dir` at `options.times` and compares them with
+/// their references.
+pub fn run_golden(
+    renderer: &mut HeadlessRenderer,
+    dir: &Path,
+    options: &GoldenOptions,
+) -> Result<Vec<GoldenResult>> {
+    let mut results = Vec::new();
+    for shader in golden_shaders(dir)? {
+        let compiled = compile(renderer, &shader);
+        for &time in &options.times {
+            let status = match &compiled {
+                Ok(()) => run_case(renderer, &shader, time, options)
+                    .unwrap_or_else(|err| GoldenStatus::Error(format!("{:#}", err))),
+                Err(err) => GoldenStatus::Error(format!("{:#}", err)),
+            };
+            results.push(GoldenResult {
+                reference: reference_path(&shader, time),
+                shader: shader.clone(),
+                time,
+                status,
+            });
+        }
+    }
+    Ok(results)
+}
+
+fn compile(renderer: &mut HeadlessRenderer, shader: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(shader)
+        .with_context(|| format!("Failed to read {}", shader.display()))?;
+    let language = ShaderLanguage::from_path(shader).context("Unknown shader language")?;
+    let mut diagnostics = Vec::new();
+    renderer
+        .set_shaders(
+            &content,
+            language,
+            &CompileSettings::default(),
+            &ComputeSettings::default(),
+            OutputSettings::default(),
+            &mut diagnostics,
+        )
+        .map_err(|err| {
+            let messages: Vec<String> = Diagnostic::from_error(&err)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            anyhow::anyhow!("does not compile: {}", messages.join("\n"))
+        })
+}
+
+fn run_case(
+    renderer: &mut HeadlessRenderer,
+    shader: &Path,
+    time: f32,
+    options: &GoldenOptions,
+) -> Result<GoldenStatus> {
+    let clock = FrameClock::default();
+    let frame = clock.frame_at(time);
+    renderer.warm_up(options.size, &clock, frame)?;
+    let image = renderer.render(&frame_uniform(
+        options.size,
+        time,
+        clock.time_delta(),
+        frame,
+    ))?;
+
+    let reference = reference_path(shader, time);
+    let exists = reference.exists();
+    if options.update || (!exists && options.create_missing) {
+        if let Some(dir) = reference.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        image.write_png(&reference)?;
+        return Ok(if exists {
+            GoldenStatus::Updated
+        } else {
+            GoldenStatus::Created
+        });
+    }
+    if !exists {
+        return Ok(GoldenStatus::Missing);
+    }
+
+    let expected = RgbaImage::read_png(&reference)?;
+    let comparison = compare_images(&expected, &image, options.tolerance.threshold)?;
+    let mismatch = comparison.mismatch();
+    if mismatch <= options.tolerance.max_mismatch {
+        return Ok(GoldenStatus::Passed { mismatch });
+    }
+    std::fs::create_dir_all(&options.diff_dir)
+        .with_context(|| format!("Failed to create {}", options.diff_dir.display()))?;
+    let name = case_name(shader, time);
+    image.write_png(&options.diff_dir.join(format!("{}.actual.png", name)))?;
+    let diff = options.diff_dir.join(format!("{}.diff.png", name));
+    comparison.diff.write_png(&diff)?;
+    Ok(GoldenStatus::Failed { mismatch, diff })
+}
+
+/// Result of [`compare_images`].
+pub struct ImageComparison {
+    /// Pixels that differ by more than the threshold.
+    pub mismatched: usize,
+    pub pixels: usize,
+    /// The reference faded to grey, with the mismatched pixels in red.
+    pub diff: RgbaImage,
+}
+
+impl ImageComparison {
+    /// Fraction of the pixels that differ.
+    pub fn mismatch(&self) -> f64 {
+        self.mismatched as f64 / self.pixels.max(1) as f64
+    }
+}
+
+/// Compares two images of the same size pixel by pixel; pixels whose YIQ
+/// difference exceeds `threshold` (0..1) count as mismatched.
+pub fn compare_images(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    threshold: f32,
+) -> Result<ImageComparison> {
+    if [expected.width, expected.height] != [actual.width, actual.height] {
+        bail!(
+            "The image is {}x{}, the reference {}x{}",
+            actual.width,
+            actual.height,
+            expected.width,
+            expected.height
+        );
+    }
+    let max_delta = MAX_YIQ_DELTA * threshold * threshold;
+    let mut mismatched = 0;
+    let mut diff = Vec::with_capacity(expected.pixels.len());
+    for (expected, actual) in expected
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+    {
+        let (expected, actual) = (blend_on_white(expected), blend_on_white(actual));
+        if yiq_delta(expected, actual) > max_delta {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let grey = (255.0 + (luma(expected) - 255.0) * 0.1) as u8;
+            diff.extend_from_slice(&[grey, grey, grey, 255]);
+        }
+    }
+    Ok(ImageComparison {
+        mismatched,
+        pixels: expected.pixels.len() / 4,
+        diff: RgbaImage {
+            width: expected.width,
+            height: expected.height,
+            pixels: diff,
+        },
+    })
+}
+
+/// Composites a pixel over white, so transparency counts as a change.
+fn blend_on_white(pixel: &[u8]) -> [f32; 3] {
+    let alpha = f32::from(pixel[3]) / 255.0;
+    [0, 1, 2].map(|channel| 255.0 + (f32::from(pixel[channel]) - 255.0) * alpha)
+}
+
+fn luma(rgb: [f32; 3]) -> f32 {
+    rgb[0] * 0.298_895_3 + rgb[1] * 0.586_622_5 + rgb[2] * 0.114_482_23
+}
+
+/// Squared YIQ distance, weighted as in Kotsarenko and Ramos' "Measuring
+/// perceived color difference using YIQ NTSC transmission color space".
+fn yiq_delta(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let i = |rgb: [f32; 3]| rgb[0] * 0.595_978 - rgb[1] * 0.274_176_1 - rgb[2] * 0.321_801_9;
+    let q = |rgb: [f32; 3]| rgb[0] * 0.211_470_17 - rgb[1] * 0.522_617_1 + rgb[2] * 0.311_146_9;
+    let y = luma(a) - luma(b);
+    let i = i(a) - i(b);
+    let q = q(a) - q(b);
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+#[test]
+fn perceptual_comparison() {
+    let image = |pixels: &[[u8; 4]]| RgbaImage {
+        width: pixels.len() as u32,
+        height: 1,
+        pixels: pixels.concat(),
+    };
+    let expected = image(&[[0, 0, 0, 255], [128, 128, 128, 255], [255, 0, 0, 255]]);
+    let actual = image(&[[2, 1, 0, 255], [128, 128, 128, 255], [0, 0, 255, 255]]);
+    let comparison = compare_images(&expected, &actual, 0.1).unwrap();
+    assert_eq!(comparison.mismatched, 1);
+    assert_eq!(&comparison.diff.pixels[8..], &[255, 0, 0, 255]);
+    assert_eq!(
+        compare_images(&expected, &expected, 0.0)
+            .unwrap()
+            .mismatched,
+        0
+    );
+    assert!(compare_images(&expected, &image(&[[0, 0, 0, 255]]), 0.1).is_err());
+}
+
+#[test]
+fn reference_paths() {
+    assert_eq!(
+        reference_path(Path::new("shaders/rings.glsl"), 1.5),
+        Path::new("shaders/golden/rings.glsl@1.5.png")
+    );
+}
+
+/// Renders `src/app/test/golden` on a software adapter and compares it with
+/// the committed references. Set `GOLDEN_UPDATE=1` to replace them after an
+/// intended change.
+#[test]
+fn golden_images() {
+    let Some(mut renderer) = crate::app::test_renderer() else {
+        return;
+    };
+    let options = GoldenOptions {
+        diff_dir: PathBuf::from("target/golden-diff"),
+        update: std::env::var_os("GOLDEN_UPDATE").is_some(),
+        create_missing: false,
+        ..GoldenOptions::default()
+    };
+    let results = run_golden(&mut renderer, Path::new("src/app/test/golden"), &options).unwrap();
+    let failures: Vec<String> = results
+        .iter()
+        .filter(|result| result.status.is_failure())
+        .map(ToString::to_string)
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,675 @@
+//! Renders shaders without a window, for the command line and tests. The
+//! frame goes through the same templates, pipeline and output stage as the
+//! app, into a float texture that is read back to memory.
+
+use crate::app::{
+    create_compute_pipeline, create_pipeline, load_compute_shader, load_fragment_shader,
+    load_vertex_shader, read_float_texture, with_error_scope, CompileSettings, ComputeResources,
+    ComputeSettings, Diagnostic, ExportPass, FragCoordConvention, FrameClock, OutputSettings,
+    RenderMode, Renderer, Result, ShaderCache, ShaderLanguage, WgpuUniform,
+};
+use anyhow::{anyhow, bail, Context as _};
+use eframe::wgpu;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Format the output stage writes for readback. It holds the same values the
+/// app shows on screen, unquantized so accumulated samples can be averaged
+/// before rounding to 8 bits.
+const READBACK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+/// Readback format for adapters that cannot render to [`READBACK_FORMAT`],
+/// such as GLES without `EXT_color_buffer_float`. Its 11-bit mantissa is
+/// still finer than 8-bit output.
+const FALLBACK_READBACK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Largest band of 8-bit rows `render_tiled` holds before writing it out.
+/// Bands of wide images are shorter than the tiles, so their memory does not
+/// grow with the tile size.
+const MAX_BAND_BYTES: usize = 16 << 20;
+
+/// Vertices of the full-screen quad the fragment template is drawn with.
+const FRAGMENT_VERTEX_COUNT: u32 = 6;
+
+/// An 8-bit RGBA image, rows top to bottom without padding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn write_png(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Reads a PNG of any color type and bit depth, converted to 8-bit RGBA.
+    pub fn read_png(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let data = &buffer[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data.to_vec(),
+            png::ColorType::Rgb => data
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks_exact(2)
+                .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                .collect(),
+            png::ColorType::Grayscale => data
+                .iter()
+                .flat_map(|&grey| [grey, grey, grey, 255])
+                .collect(),
+            png::ColorType::Indexed => {
+                bail!("{} was not expanded from its palette", path.display())
+            }
+        };
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+/// A 32-bit float RGBA image, rows top to bottom without padding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+impl FloatImage {
+    /// Quantizes to 8 bits, clamping to 0..1.
+    pub fn to_rgba8(&self) -> RgbaImage {
+        RgbaImage {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect(),
+        }
+    }
+}
+
+/// Element `index` of the Halton sequence in `base`, in 0..1. Successive
+/// elements fill the interval evenly, so any prefix of them makes a good set
+/// of jitter offsets.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Uniforms for rendering frame `frame` at `time` seconds into a `size` image,
+/// with Shadertoy's fragCoord convention.
+pub fn frame_uniform(size: [u32; 2], time: f32, time_delta: f32, frame: u32) -> WgpuUniform {
+    let size = [size[0] as f32, size[1] as f32];
+    let [scale_x, scale_y, offset_x, offset_y] =
+        FragCoordConvention::default().transform([0.0, 0.0], size);
+    WgpuUniform {
+        resolution: std140::vec2(size[0], size[1]),
+        time: std140::float(time),
+        time_delta: std140::float(time_delta),
+        frame: std140::float(frame as f32),
+        frag_coord_transform: std140::vec4(scale_x, scale_y, offset_x, offset_y),
+        vertex_count: std140::float(FRAGMENT_VERTEX_COUNT as f32),
+        ..WgpuUniform::default()
+    }
+}
+
+pub struct HeadlessRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    adapter_info: wgpu::AdapterInfo,
+    readback_format: wgpu::TextureFormat,
+    renderer: Renderer,
+    output: OutputSettings,
+    /// Storage sizes of the compute passes, when there are any.
+    compute: Option<ComputeSettings>,
+    cache: ShaderCache,
+}
+
+impl HeadlessRenderer {
+    /// Opens a device without a surface. Software adapters (llvmpipe,
+    /// lavapipe, WARP) are used when `software` is set or no hardware adapter
+    /// is available. `WGPU_BACKEND` and the other wgpu environment variables
+    /// are honoured.
+    pub fn new(software: bool) -> Result<Self> {
+        pollster::block_on(Self::new_async(software))
+    }
+
+    async fn new_async(software: bool) -> Result<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let mut options = wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: software,
+            compatible_surface: None,
+        };
+        let mut adapter = instance.request_adapter(&options).await;
+        if adapter.is_none() && !software {
+            options.force_fallback_adapter = true;
+            adapter = instance.request_adapter(&options).await;
+        }
+        let adapter = adapter.ok_or_else(|| anyhow!("No graphics adapter found"))?;
+        let adapter_info = adapter.get_info();
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("headless_device"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: adapter.limits(),
+                    memory_hints: wgpu::MemoryHints::default(),
+                },
+                None,
+            )
+            .await
+            .with_context(|| format!("Failed to open {}", adapter_info.name))?;
+        let readback_format = if adapter
+            .get_texture_format_features(READBACK_FORMAT)
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        {
+            READBACK_FORMAT
+        } else {
+            FALLBACK_READBACK_FORMAT
+        };
+        let renderer = with_error_scope(&device, "the output stage", || {
+            Renderer::new(&device, readback_format)
+        })?;
+        Ok(Self {
+            device,
+            queue,
+            adapter_info,
+            readback_format,
+            renderer,
+            output: OutputSettings::default(),
+            compute: None,
+            cache: ShaderCache::default(),
+        })
+    }
+
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Compiles `content` into the image pass and the enabled passes of
+    /// `compute` into compute pipelines, with freshly cleared storage.
+    /// Compiler warnings are pushed to `diagnostics`; errors are returned.
+    pub fn set_shaders(
+        &mut self,
+        content: &str,
+        language: ShaderLanguage,
+        settings: &CompileSettings,
+        compute: &ComputeSettings,
+        output: OutputSettings,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<()> {
+        let settings = CompileSettings {
+            compute: compute.is_active(),
+            ..settings.clone()
+        };
+        let vertex = load_vertex_shader(&settings, &mut self.cache, diagnostics)
+            .context("Error loading vertex shader")?;
+        let fragment =
+            load_fragment_shader(content, language, &settings, &mut self.cache, diagnostics)
+                .context("Error loading fragment shader")?;
+        let compute_pipelines = compute
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| pass.enabled)
+            .map(|(index, pass)| {
+                let spirv =
+                    load_compute_shader(&pass.content, &settings, &mut self.cache, diagnostics)
+                        .with_context(|| format!("Error loading compute pass {}", index + 1))?;
+                let pipeline = create_compute_pipeline(&self.device, spirv)?;
+                Ok((pipeline, pass.workgroups))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let format = output.format.to_wgpu();
+        let pipeline = create_pipeline(
+            &self.device,
+            vertex,
+            &fragment,
+            format,
+            wgpu::PrimitiveTopology::TriangleList,
+            settings
+                .compute
+                .then_some(RenderMode::Fragment.compute_stages()),
+        )?;
+        self.renderer
+            .set_pipeline(pipeline, format, settings.compute);
+        self.renderer.compute_pipelines = compute_pipelines;
+        self.compute = settings.compute.then(|| compute.clone());
+        self.output = output;
+        self.reset()
+    }
+
+    /// Whether frames depend on the ones before them, through the compute
+    /// storage.
+    pub fn is_stateful(&self) -> bool {
+        self.compute.is_some()
+    }
+
+    /// Clears the compute storage, as restarting playback in the app does.
+    pub fn reset(&mut self) -> Result<()> {
+        self.renderer.compute = self
+            .compute
+            .as_ref()
+            .map(|compute| {
+                ComputeResources::new(
+                    &self.device,
+                    self.renderer.uniform_buffer(),
+                    compute,
+                    RenderMode::Fragment.compute_stages(),
+                )
+            })
+            .transpose()?;
+        Ok(())
+    }
+
+    /// Resets stateful shaders and steps them through the frames before
+    /// `frame`, so the compute storage is where live playback would have it.
+    /// Does nothing for stateless shaders.
+    pub fn warm_up(&mut self, size: [u32; 2], clock: &FrameClock, frame: u32) -> Result<()> {
+        if !self.is_stateful() {
+            return Ok(());
+        }
+        self.reset()?;
+        for frame in 0..frame {
+            self.step(&frame_uniform(
+                size,
+                clock.time(frame),
+                clock.time_delta(),
+                frame,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Renders `frames` of a `size` animation timed by `clock`, passing each
+    /// one to `write` in order. The compute storage advances in lockstep, so
+    /// every frame matches live playback.
+    pub fn render_frames(
+        &mut self,
+        size: [u32; 2],
+        clock: &FrameClock,
+        frames: RangeInclusive<u32>,
+        mut write: impl FnMut(u32, RgbaImage) -> Result<()>,
+    ) -> Result<()> {
+        self.warm_up(size, clock, *frames.start())?;
+        for frame in frames {
+            let uniform = frame_uniform(size, clock.time(frame), clock.time_delta(), frame);
+            write(frame, self.render(&uniform)?)?;
+        }
+        Ok(())
+    }
+
+    /// Runs a frame's compute and image passes without reading it back.
+    fn step(&mut self, uniform: &WgpuUniform) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("headless_step_encoder"),
+            });
+        self.renderer.prepare(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            uniform,
+            FRAGMENT_VERTEX_COUNT,
+            &self.output,
+        );
+        self.queue.submit([encoder.finish()]);
+    }
+
+    /// Renders one frame and reads it back. The image size is taken from
+    /// `uniform.resolution`.
+    pub fn render(&mut self, uniform: &WgpuUniform) -> Result<RgbaImage> {
+        let size = [uniform.resolution.0 as u32, uniform.resolution.1 as u32];
+        Ok(self
+            .render_region(uniform, size, true, ExportPass::Output)?
+            .to_rgba8())
+    }
+
+    /// Renders one frame and reads back `pass` unclamped. The image pass is
+    /// only as precise as `OutputSettings::format`.
+    pub fn render_float(&mut self, uniform: &WgpuUniform, pass: ExportPass) -> Result<FloatImage> {
+        let size = [uniform.resolution.0 as u32, uniform.resolution.1 as u32];
+        self.render_region(uniform, size, true, pass)
+    }
+
+    /// Renders the frame `uniform` describes `grid`x`grid` times per pixel,
+    /// once for each cell of a grid over the pixel, and repeats that
+    /// `frames` times with the samples shifted within their cells, averaging
+    /// everything in float precision. The shader sees each sample's offset in
+    /// `iJitter`. Compute passes run once. `pass` is the image averaged, and
+    /// `progress` is called after every sample with the number done and the
+    /// total.
+    pub fn render_accumulated(
+        &mut self,
+        uniform: &WgpuUniform,
+        pass: ExportPass,
+        grid: u32,
+        frames: u32,
+        mut progress: impl FnMut(u32, u32),
+    ) -> Result<FloatImage> {
+        let size = [uniform.resolution.0 as u32, uniform.resolution.1 as u32];
+        let (grid, frames) = (grid.max(1), frames.max(1));
+        let total = grid * grid * frames;
+        let mut uniform = uniform.clone();
+        let mut sum: Option<FloatImage> = None;
+        let mut done = 0;
+        for frame in 0..frames {
+            // A single frame samples the cell centers; more spread over the
+            // cells along a low-discrepancy sequence.
+            let within = if frames == 1 {
+                [0.5, 0.5]
+            } else {
+                [halton(frame + 1, 2), halton(frame + 1, 3)]
+            };
+            for cell in 0..grid * grid {
+                let cell = [cell % grid, cell / grid];
+                uniform.jitter = std140::vec2(
+                    (cell[0] as f32 + within[0]) / grid as f32 - 0.5,
+                    (cell[1] as f32 + within[1]) / grid as f32 - 0.5,
+                );
+                let image = self.render_region(&uniform, size, done == 0, pass)?;
+                match &mut sum {
+                    Some(sum) => {
+                        for (sum, value) in sum.pixels.iter_mut().zip(&image.pixels) {
+                            *sum += value;
+                        }
+                    }
+                    None => sum = Some(image),
+                }
+                done += 1;
+                progress(done, total);
+            }
+        }
+        let mut image = sum.expect("at least one sample is rendered");
+        for value in &mut image.pixels {
+            *value /= total as f32;
+        }
+        Ok(image)
+    }
+
+    /// Largest square tile the device can render in one piece: within the
+    /// texture size limit, with a readback buffer within the buffer size
+    /// limit.
+    pub fn max_tile_size(&self) -> u32 {
+        let limits = self.device.limits();
+        let texel_bytes = self.readback_texel_bytes();
+        let by_buffer = ((limits.max_buffer_size / u64::from(texel_bytes)) as f64).sqrt() as u32;
+        // Whole rows need no padding, so the buffer is exactly tile² texels.
+        let row_alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / texel_bytes;
+        let by_buffer = (by_buffer / row_alignment * row_alignment).max(row_alignment);
+        limits.max_texture_dimension_2d.min(by_buffer)
+    }
+
+    fn readback_texel_bytes(&self) -> u32 {
+        self.readback_format.block_copy_size(None).unwrap_or(16)
+    }
+
+    /// Bytes of the buffer a `size` frame is read back through.
+    fn readback_buffer_size(&self, [width, height]: [u32; 2]) -> u64 {
+        let row_bytes = (width * self.readback_texel_bytes())
+            .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        u64::from(row_bytes) * u64::from(height)
+    }
+
+    /// Renders a `size` image, which may exceed the device's texture limits, in
+    /// tiles of at most `tile_size` pixels. Every tile sees the whole image's
+    /// `iResolution` and `fragCoord`, and the compute passes run once. Tiles
+    /// are stitched into bands of whole rows that are passed to `write_rows`
+    /// top to bottom, so only one band of at most 16 MiB is in memory at
+    /// a time.
+    pub fn render_tiled(
+        &mut self,
+        size: [u32; 2],
+        tile_size: u32,
+        time: f32,
+        time_delta: f32,
+        frame: u32,
+        mut write_rows: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let tile_size = tile_size.clamp(1, self.max_tile_size());
+        let mut uniform = frame_uniform(size, time, time_delta, frame);
+        let full_size = [size[0] as f32, size[1] as f32];
+        let row_bytes = size[0] as usize * 4;
+        let band_height = (MAX_BAND_BYTES / row_bytes).clamp(1, tile_size as usize);
+        let mut run_compute = true;
+        for top in (0..size[1]).step_by(band_height) {
+            let height = (band_height as u32).min(size[1] - top);
+            let mut band = vec![0; row_bytes * height as usize];
+            for left in (0..size[0]).step_by(tile_size as usize) {
+                let width = tile_size.min(size[0] - left);
+                // The tile's target starts at (left, top) of the whole image,
+                // so the image's viewport starts that far before it.
+                let [scale_x, scale_y, offset_x, offset_y] = FragCoordConvention::default()
+                    .transform([-(left as f32), -(top as f32)], full_size);
+                uniform.frag_coord_transform = std140::vec4(scale_x, scale_y, offset_x, offset_y);
+                let tile = self
+                    .render_region(&uniform, [width, height], run_compute, ExportPass::Output)?
+                    .to_rgba8();
+                run_compute = false;
+                let tile_row_bytes = width as usize * 4;
+                for (row, pixels) in tile.pixels.chunks_exact(tile_row_bytes).enumerate() {
+                    let offset = row * row_bytes + left as usize * 4;
+                    band[offset..offset + tile_row_bytes].copy_from_slice(pixels);
+                }
+            }
+            write_rows(&band)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a `size` region of the frame `uniform` describes and reads back
+    /// `pass`; `uniform.frag_coord_transform` places the region in the image.
+    /// wgpu errors are returned rather than panicking.
+    fn render_region(
+        &mut self,
+        uniform: &WgpuUniform,
+        size: [u32; 2],
+        run_compute: bool,
+        pass: ExportPass,
+    ) -> Result<FloatImage> {
+        let [width, height] = size.map(|side| side.max(1));
+        let limits = self.device.limits();
+        let max_size = limits.max_texture_dimension_2d;
+        if width > max_size || height > max_size {
+            bail!(
+                "{}x{} is larger than the {} pixels this adapter can render at once; \
+                 render it in tiles with `poster`",
+                width,
+                height,
+                max_size
+            );
+        }
+        if self.readback_buffer_size([width, height]) > limits.max_buffer_size {
+            bail!(
+                "{}x{} needs a larger readback buffer than this adapter allows; \
+                 render it in tiles with `poster`",
+                width,
+                height
+            );
+        }
+        let device = self.device.clone();
+        with_error_scope(&device, "the frame", || {
+            self.render_region_unchecked(uniform, [width, height], run_compute, pass)
+        })?
+    }
+
+    fn render_region_unchecked(
+        &mut self,
+        uniform: &WgpuUniform,
+        [width, height]: [u32; 2],
+        run_compute: bool,
+        pass: ExportPass,
+    ) -> Result<FloatImage> {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("readback_target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.readback_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("headless_encoder"),
+            });
+        self.renderer
+            .write_uniforms(&self.queue, uniform, &self.output);
+        if run_compute {
+            self.renderer.dispatch_compute(&mut encoder);
+        }
+        self.renderer.draw_image(
+            &self.device,
+            &mut encoder,
+            [width, height],
+            FRAGMENT_VERTEX_COUNT,
+            self.output.format,
+        );
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("output_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.renderer.paint(&mut pass);
+        }
+
+        let texture = match pass {
+            ExportPass::Output => &texture,
+            ExportPass::Image => {
+                &self
+                    .renderer
+                    .target()
+                    .context("The image pass has no target")?
+                    .texture
+            }
+            ExportPass::ComputeImage => {
+                &self
+                    .renderer
+                    .compute
+                    .as_ref()
+                    .context("The shader has no compute passes")?
+                    .image
+            }
+        };
+        read_float_texture(&self.device, &self.queue, encoder, texture)
+    }
+}
+
+#[test]
+fn halton_sequence() {
+    let offsets: Vec<f32> = (1..5).map(|index| halton(index, 2)).collect();
+    assert_eq!(offsets, [0.5, 0.25, 0.75, 0.125]);
+    assert!((halton(2, 3) - 2.0 / 3.0).abs() < 1e-6);
+}
+
+/// A software renderer for the tests that draw. They fail without an adapter
+/// unless `SKIP_GPU_TESTS` is set.
+#[cfg(test)]
+pub fn test_renderer() -> Option<HeadlessRenderer> {
+    if std::env::var_os("SKIP_GPU_TESTS").is_some() {
+        eprintln!("SKIP_GPU_TESTS is set, not rendering");
+        return None;
+    }
+    match HeadlessRenderer::new(true) {
+        Ok(renderer) => Some(renderer),
+        Err(err) => panic!("{:#}; set SKIP_GPU_TESTS to skip the rendering tests", err),
+    }
+}
+
+/// fragCoord starts at the bottom left pixel's center in every language, as
+/// on Shadertoy.
+#[test]
+fn frag_coord_origin_is_bottom_left() {
+    let Some(mut renderer) = test_renderer() else {
+        return;
+    };
+    for language in ShaderLanguage::ALL {
+        let path =
+            Path::new("src/app/test/naga/pass/gradient").with_extension(language.extensions()[0]);
+        let content = std::fs::read_to_string(&path).unwrap();
+        renderer
+            .set_shaders(
+                &content,
+                language,
+                &CompileSettings::default(),
+                &ComputeSettings::default(),
+                OutputSettings::default(),
+                &mut Vec::new(),
+            )
+            .unwrap_or_else(|err| panic!("{}: {:#}", path.display(), err));
+        // The gradient shader writes fragCoord / iResolution to red and green.
+        let image = renderer
+            .render(&frame_uniform([16, 8], 0.0, 0.0, 0))
+            .unwrap();
+        let pixel = |x: u32, y: u32| {
+            let offset = ((y * image.width + x) * 4) as usize;
+            [image.pixels[offset], image.pixels[offset + 1]]
+        };
+        // Rows are stored top to bottom; 8 = 255 * 0.5 / 16 and 16 = 255 * 0.5 / 8.
+        assert_eq!(pixel(0, 7), [8, 16], "bottom left of {}", language.name());
+        assert_eq!(pixel(15, 0), [247, 239], "top right of {}", language.name());
+    }
+}
+
+#[test]
+fn oversized_renders_are_errors() {
+    let Some(mut renderer) = test_renderer() else {
+        return;
+    };
+    let size = [renderer.device.limits().max_texture_dimension_2d + 1, 1];
+    let err = renderer
+        .render(&frame_uniform(size, 0.0, 0.0, 0))
+        .unwrap_err();
+    assert!(err.to_string().contains("poster"), "{:#}", err);
+}
@@ -0,0 +1,161 @@
+use crate::app::CompiledShader;
+use eframe::egui;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum IrTab {
+    SpirV,
+    Wgsl,
+    Statistics,
+}
+
+impl IrTab {
+    const ALL: [Self; 3] = [Self::SpirV, Self::Wgsl, Self::Statistics];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::SpirV => "SPIR-V",
+            Self::Wgsl => "WGSL",
+            Self::Statistics => "Statistics",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::SpirV => "shader.spvasm",
+            Self::Wgsl => "shader.wgsl",
+            Self::Statistics => "shader_stats.txt",
+        }
+    }
+}
+
+/// Sizes of the module the GPU receives, as seen by shaderc and naga.
+#[derive(Default)]
+pub struct ModuleStats {
+    pub spirv_words: usize,
+    pub id_bound: u32,
+    pub entry_points: usize,
+    pub functions: usize,
+    pub global_variables: usize,
+    pub types: usize,
+    pub constants: usize,
+    pub expressions: usize,
+}
+
+impl std::fmt::Display for ModuleStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "SPIR-V size: {} words ({} bytes)",
+            self.spirv_words,
+            self.spirv_words * 4
+        )?;
+        writeln!(f, "ID bound: {}", self.id_bound)?;
+        writeln!(f, "Entry points: {}", self.entry_points)?;
+        writeln!(f, "Functions: {}", self.functions)?;
+        writeln!(f, "Global variables: {}", self.global_variables)?;
+        writeln!(f, "Types: {}", self.types)?;
+        writeln!(f, "Constants: {}", self.constants)?;
+        write!(f, "Expressions: {}", self.expressions)
+    }
+}
+
+/// What the GPU actually gets for the current pass: the SPIR-V disassembly,
+/// naga's WGSL translation of it and some module statistics.
+pub struct ShaderIr {
+    spirv_assembly: String,
+    wgsl: String,
+    stats: String,
+    tab: IrTab,
+}
+
+impl ShaderIr {
+    /// `spirv_assembly` is the disassembly of the shader when a disassembler is
+    /// available, or the reason it is not.
+    pub fn new(shader: &CompiledShader, spirv_assembly: crate::app::Result<String>) -> Self {
+        let mut stats = ModuleStats::default();
+        if let CompiledShader::SpirV(spirv) = shader {
+            stats.spirv_words = spirv.len();
+            // Word 3 of the SPIR-V header is the bound on result IDs.
+            stats.id_bound = spirv.get(3).copied().unwrap_or_default();
+        }
+        let wgsl = match shader.validate() {
+            Ok(validated) => {
+                let module = &validated.module;
+                stats.entry_points = module.entry_points.len();
+                stats.functions = module.functions.len();
+                stats.global_variables = module.global_variables.len();
+                stats.types = module.types.len();
+                stats.constants = module.constants.len();
+                stats.expressions = module
+                    .functions
+                    .iter()
+                    .map(|(_, function)| function.expressions.len())
+                    .chain(
+                        module
+                            .entry_points
+                            .iter()
+                            .map(|e| e.function.expressions.len()),
+                    )
+                    .sum();
+                naga::back::wgsl::write_string(
+                    module,
+                    &validated.info,
+                    naga::back::wgsl::WriterFlags::EXPLICIT_TYPES,
+                )
+                .unwrap_or_else(|err| format!("// Failed to write WGSL: {}", err))
+            }
+            Err(err) => format!("// {}", err),
+        };
+        Self {
+            spirv_assembly: spirv_assembly
+                .unwrap_or_else(|err| format!("; Disassembly unavailable: {}", err)),
+            wgsl,
+            stats: stats.to_string(),
+            tab: IrTab::SpirV,
+        }
+    }
+
+    fn text(&self, tab: IrTab) -> &str {
+        match tab {
+            IrTab::SpirV => &self.spirv_assembly,
+            IrTab::Wgsl => &self.wgsl,
+            IrTab::Statistics => &self.stats,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for tab in IrTab::ALL {
+                ui.selectable_value(&mut self.tab, tab, tab.label());
+            }
+            ui.separator();
+            if ui.button("📋 Copy").clicked() {
+                ui.ctx().copy_text(self.text(self.tab).to_owned());
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if ui.button("💾 Save").clicked() {
+                if let Some(path) = rfd::FileDialog::new()
+                    .set_file_name(self.tab.file_name())
+                    .save_file()
+                {
+                    match std::fs::write(&path, self.text(self.tab)) {
+                        Ok(_) => log::info!("Saved {}", path.display()),
+                        Err(err) => log::error!("Failed to save {}: {}", path.display(), err),
+                    }
+                }
+            }
+        });
+        let mut text = self.text(self.tab);
+        egui::ScrollArea::both()
+            .id_salt("ir_scroll_area")
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(&mut text)
+                        .font(egui::TextStyle::Monospace)
+                        .code_editor()
+                        .desired_width(f32::INFINITY),
+                );
+            });
+    }
+}
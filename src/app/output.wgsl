@@ -0,0 +1,50 @@
+// Tone-maps the offscreen image into the output target.
+
+struct Output {
+    exposure_scale: f32,
+    // 0: clamp, 1: Reinhard, 2: ACES.
+    tone_mapping: u32,
+}
+
+@group(0) @binding(0) var image: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> output: Output;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the viewport, with uv (0, 0) at its top-left.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let position = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = vec2<f32>(position.x, -position.y) * 0.5 + 0.5;
+    return out;
+}
+
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(image));
+    let texel = vec2<i32>(min(in.uv * size, size - 1.0));
+    let color = textureLoad(image, texel, 0).rgb * output.exposure_scale;
+    var mapped: vec3<f32>;
+    switch output.tone_mapping {
+        case 1u: {
+            let positive = max(color, vec3<f32>(0.0));
+            mapped = positive / (1.0 + positive);
+        }
+        case 2u: {
+            mapped = aces(max(color, vec3<f32>(0.0)));
+        }
+        default: {
+            mapped = color;
+        }
+    }
+    return vec4<f32>(clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
@@ -0,0 +1,286 @@
+use crate::app::{CompileSettings, Diagnostic, ShaderCache};
+
+/// Built-ins and keywords that GLSL ES 3.10 adds on top of 3.00. The check
+/// compiles under 3.10 because glslang cannot emit SPIR-V for older ES
+/// versions, so uses of these are reported separately.
+const ES_310_ONLY: &[&str] = &[
+    "bitfieldExtract",
+    "bitfieldInsert",
+    "bitfieldReverse",
+    "bitCount",
+    "findLSB",
+    "findMSB",
+    "uaddCarry",
+    "usubBorrow",
+    "umulExtended",
+    "imulExtended",
+    "frexp",
+    "ldexp",
+    "packUnorm4x8",
+    "packSnorm4x8",
+    "unpackUnorm4x8",
+    "unpackSnorm4x8",
+    "textureGather",
+    "textureGatherOffset",
+    "imageLoad",
+    "imageStore",
+    "imageSize",
+    "atomicAdd",
+    "atomicCounter",
+    "memoryBarrier",
+    "barrier",
+    "buffer",
+    "shared",
+];
+
+/// Compiles `content` the way Shadertoy's WebGL2 (GLSL ES 3.00) would see it,
+/// in addition to the normal desktop compile, and returns what would not port:
+/// implicit int/float conversions, missing precision, desktop-only built-ins,
+/// non-constant loop bounds and so on. Nothing here stops the desktop pipeline
+/// from being used.
+pub fn check_es_portability(
+    content: &str,
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+) -> Vec<Diagnostic> {
+    let code = strip_comments(content);
+    let mut diagnostics = es_310_only_identifiers(&code);
+    diagnostics.extend(non_constant_loops(&code));
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = compile_es(content, settings, cache) {
+        diagnostics.extend(Diagnostic::from_error(&err));
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = (settings, cache);
+    for diagnostic in &mut diagnostics {
+        diagnostic.message.insert_str(0, "WebGL2: ");
+    }
+    diagnostics
+}
+
+/// Compiles `content` into the ES template through `cache`. ES warnings are
+/// not reported, only errors.
+#[cfg(not(target_arch = "wasm32"))]
+fn compile_es(
+    content: &str,
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+) -> crate::app::Result<()> {
+    let settings = CompileSettings {
+        warnings_as_errors: false,
+        compute: false,
+        ..settings.clone()
+    };
+    crate::app::convert_shader_cached(
+        cache,
+        &mut Vec::new(),
+        include_str!("shader_es.frag"),
+        content,
+        shaderc::ShaderKind::Fragment,
+        crate::app::ShaderLanguage::Glsl,
+        &settings,
+    )?;
+    Ok(())
+}
+
+/// `content` line by line with comments replaced by spaces.
+fn strip_comments(content: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut in_block_comment = false;
+    for line in content.lines() {
+        let mut code = String::new();
+        let mut rest = line;
+        loop {
+            if in_block_comment {
+                match rest.find("*/") {
+                    Some(end) => {
+                        rest = &rest[end + 2..];
+                        in_block_comment = false;
+                    }
+                    None => break,
+                }
+            } else {
+                let line_comment = rest.find("//");
+                let block_comment = rest.find("/*");
+                match (line_comment, block_comment) {
+                    (Some(line_start), Some(block_start)) if line_start < block_start => {
+                        code.push_str(&rest[..line_start]);
+                        break;
+                    }
+                    (Some(line_start), None) => {
+                        code.push_str(&rest[..line_start]);
+                        break;
+                    }
+                    (_, Some(block_start)) => {
+                        code.push_str(&rest[..block_start]);
+                        code.push(' ');
+                        rest = &rest[block_start + 2..];
+                        in_block_comment = true;
+                    }
+                    (None, None) => {
+                        code.push_str(rest);
+                        break;
+                    }
+                }
+            }
+        }
+        lines.push(code);
+    }
+    lines
+}
+
+fn identifiers(code: &str) -> impl Iterator<Item = &str> {
+    code.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+}
+
+/// Finds identifiers from [`ES_310_ONLY`] in `code`, as returned by
+/// [`strip_comments`]. Line numbers are relative to the user's code.
+fn es_310_only_identifiers(code: &[String]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (index, line) in code.iter().enumerate() {
+        for identifier in identifiers(line) {
+            if ES_310_ONLY.contains(&identifier) {
+                diagnostics.push(Diagnostic::error(format!(
+                    "line {}: `{}` is not available in GLSL ES 3.00",
+                    index + 1,
+                    identifier
+                )));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Names that may appear in a constant loop bound besides literals and the
+/// shader's own constants.
+const CONSTANT_FUNCTIONS: &[&str] = &[
+    "int", "uint", "float", "abs", "min", "max", "clamp", "sign", "floor", "ceil",
+];
+
+/// Finds `for` loops whose index is not initialised, bounded and stepped by
+/// constant expressions, as GLSL ES 1.00 requires. WebGL2 accepts them, but
+/// ANGLE unrolls loops on some drivers and they are a common reason shaders
+/// fail to port.
+fn non_constant_loops(code: &[String]) -> Vec<Diagnostic> {
+    let source = code.join("\n");
+    // `const` globals and locals and `#define`d names count as constants.
+    let mut constants: Vec<&str> = Vec::new();
+    for line in code {
+        let words: Vec<&str> = identifiers(line).collect();
+        if line.trim_start().starts_with('#') {
+            if let ["define", name, ..] = words[..] {
+                constants.push(name);
+            }
+        } else if let Some(position) = words.iter().position(|word| *word == "const") {
+            constants.extend(words.get(position + 2));
+        }
+    }
+    let is_constant = |expression: &str| {
+        identifiers(expression).all(|identifier| {
+            constants.contains(&identifier) || CONSTANT_FUNCTIONS.contains(&identifier)
+        })
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut search = 0;
+    while let Some(found) = source[search..].find("for") {
+        let start = search + found;
+        search = start + 3;
+        let before = source[..start].chars().next_back();
+        let after = source[start + 3..].trim_start();
+        if before.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') || !after.starts_with('(')
+        {
+            continue;
+        }
+        // The header, up to the parenthesis that closes it.
+        let open = source.len() - after.len();
+        let mut depth = 0;
+        let Some(close) = source[open..].find(|c: char| {
+            depth += match c {
+                '(' => 1,
+                ')' => -1,
+                _ => 0,
+            };
+            depth == 0
+        }) else {
+            break;
+        };
+        let header = &source[open + 1..open + close];
+        let line = source[..start].matches('\n').count() + 1;
+        let [init, condition, step] = header.splitn(3, ';').collect::<Vec<_>>()[..] else {
+            continue;
+        };
+        let Some(index) = init
+            .split_once('=')
+            .and_then(|(declaration, _)| identifiers(declaration).last())
+        else {
+            continue;
+        };
+        let init_value = init.split_once('=').map_or("", |(_, value)| value);
+        let bound = condition
+            .split(['<', '>', '=', '!'])
+            .map(str::trim)
+            .filter(|side| !side.is_empty() && *side != index)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let step_value = step.split_once('=').map_or("", |(_, value)| value);
+        for (what, expression) in [
+            ("initial value", init_value),
+            ("bound", bound.as_str()),
+            ("step", step_value),
+        ] {
+            if !is_constant(expression) {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "line {}: the {} of loop index `{}` is not a constant expression",
+                    line, what, index
+                )));
+            }
+        }
+    }
+    diagnostics
+}
+
+#[test]
+fn es_310_only_identifiers_skip_comments() {
+    let content = "int a = bitCount(x); // findLSB\n\
+                   /* ldexp\n\
+                   frexp */ float b = ldexp(c, 2);\n\
+                   float barrierHeight = 1.0;";
+    let messages: Vec<String> = es_310_only_identifiers(&strip_comments(content))
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "line 1: `bitCount` is not available in GLSL ES 3.00",
+            "line 3: `ldexp` is not available in GLSL ES 3.00",
+        ]
+    );
+}
+
+#[test]
+fn non_constant_loop_bounds() {
+    let content = "#define STEPS 64\n\
+                   const int ITERATIONS = 8;\n\
+                   void mainImage(out vec4 fragColor, in vec2 fragCoord) {\n\
+                       for (int i = 0; i < STEPS; i++) {}\n\
+                       for (int j = 0; j <= min(ITERATIONS, 4); j += 2) {}\n\
+                       int n = int(iTime);\n\
+                       for (int k = 0; k < n; ++k) {}\n\
+                       // for (int l = 0; l < n; l++)\n\
+                       for (float t = 0.0; t < 1.0; t += iTimeDelta) {}\n\
+                   }";
+    let messages: Vec<String> = non_constant_loops(&strip_comments(content))
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "line 7: the bound of loop index `k` is not a constant expression",
+            "line 9: the step of loop index `t` is not a constant expression",
+        ]
+    );
+}
@@ -0,0 +1,604 @@
+//! Draws the user's shaders. The image pass renders into an offscreen float
+//! target, and an output stage tone-maps that target into whatever the caller
+//! is drawing to: the egui surface in the app, or a plain texture when
+//! rendering without a window.
+
+use crate::app::{render_storage_layout, validate_spirv, CompiledShader, ComputeResources, Result};
+use eframe::wgpu;
+use std::borrow::Cow;
+
+/// Uniforms shared by every stage, laid out like the `Uniforms` block in the
+/// templates.
+#[derive(Clone)]
+#[std140::repr_std140]
+pub struct WgpuUniform {
+    pub resolution: std140::vec2,
+    pub time: std140::float,
+    pub time_delta: std140::float,
+    pub frame: std140::float,
+    pub channel_time: std140::vec4,
+    pub mouse: std140::vec4,
+    pub date: std140::vec4,
+    pub sample_rate: std140::float,
+    pub frag_coord_transform: std140::vec4,
+    /// Sub-pixel offset added to `fragCoord`, for supersampling.
+    pub jitter: std140::vec2,
+    pub vertex_count: std140::float,
+}
+impl Default for WgpuUniform {
+    fn default() -> Self {
+        Self {
+            resolution: std140::vec2::zero(),
+            time: std140::float(0.0),
+            time_delta: std140::float(0.0),
+            frame: std140::float(0.0),
+            channel_time: std140::vec4::zero(),
+            mouse: std140::vec4::zero(),
+            date: std140::vec4::zero(),
+            sample_rate: std140::float(0.0),
+            frag_coord_transform: std140::vec4(1.0, -1.0, 0.0, 0.0),
+            jitter: std140::vec2::zero(),
+            vertex_count: std140::float(0.0),
+        }
+    }
+}
+
+pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("bind_group_layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+    })
+}
+
+/// Polls a future once. wgpu resolves `pop_error_scope` immediately on native
+/// and WebGL because validation runs on the calling thread; only WebGPU
+/// reports `Pending`.
+fn poll_once<F: std::future::Future + ?Sized>(
+    future: std::pin::Pin<&mut F>,
+) -> std::task::Poll<F::Output> {
+    use std::task::{Context, RawWaker, RawWakerVTable, Waker};
+
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    // SAFETY: the vtable functions ignore the data pointer and do nothing.
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    future.poll(&mut Context::from_waker(&waker))
+}
+
+/// Errors of error scopes that were still pending when the object was
+/// returned, which happens on WebGPU where validation is asynchronous.
+static LATE_ERRORS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+/// Takes the errors that resolved since the last call.
+pub fn take_late_errors() -> Vec<String> {
+    std::mem::take(&mut *LATE_ERRORS.lock().unwrap_or_else(|err| err.into_inner()))
+}
+
+/// Runs `create` inside validation and internal error scopes so wgpu errors are
+/// returned instead of reaching the uncaptured error handler, which panics.
+/// When the scopes only resolve later, `create`'s value is returned and the
+/// error is queued for [`take_late_errors`].
+pub fn with_error_scope<T>(
+    device: &wgpu::Device,
+    what: &str,
+    create: impl FnOnce() -> T,
+) -> Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Internal);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    let validation_error = device.pop_error_scope();
+    let internal_error = device.pop_error_scope();
+    let mut error = Box::pin(async move {
+        let validation_error = validation_error.await;
+        validation_error.or(internal_error.await)
+    });
+    let what = what.to_owned();
+    match poll_once(error.as_mut()) {
+        std::task::Poll::Ready(Some(err)) => {
+            Err(anyhow::anyhow!("Failed to create {}: {}", what, err))
+        }
+        std::task::Poll::Ready(None) => Ok(value),
+        std::task::Poll::Pending => {
+            let report = async move {
+                if let Some(err) = error.await {
+                    let message = format!("Failed to create {}: {}", what, err);
+                    LATE_ERRORS
+                        .lock()
+                        .unwrap_or_else(|err| err.into_inner())
+                        .push(message);
+                }
+            };
+            #[cfg(target_arch = "wasm32")]
+            wasm_bindgen_futures::spawn_local(report);
+            #[cfg(not(target_arch = "wasm32"))]
+            pollster::block_on(report);
+            Ok(value)
+        }
+    }
+}
+
+/// `compute_stages` are the stages that read the compute storage from bind
+/// group 1, when compute passes are enabled.
+pub fn create_pipeline(
+    device: &wgpu::Device,
+    vertex_spirv: Cow<'_, [u32]>,
+    fragment_shader: &CompiledShader,
+    target_format: wgpu::TextureFormat,
+    topology: wgpu::PrimitiveTopology,
+    compute_stages: Option<wgpu::ShaderStages>,
+) -> Result<wgpu::RenderPipeline> {
+    // Pre-flight check, so unsupported SPIR-V is explained rather than only
+    // surfacing as a wgpu validation error.
+    validate_spirv(&vertex_spirv).map_err(|err| anyhow::anyhow!("Vertex shader: {}", err))?;
+    fragment_shader
+        .validate()
+        .map_err(|err| anyhow::anyhow!("Fragment shader: {}", err))?;
+
+    let bind_group_layout = create_bind_group_layout(device);
+    let vertex_shader = with_error_scope(device, "vertex shader module", || {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("vertex_shader"),
+            source: wgpu::ShaderSource::SpirV(vertex_spirv),
+        })
+    })?;
+    let fragment_shader = with_error_scope(device, "fragment shader module", || {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("fragment_shader"),
+            source: fragment_shader.source(),
+        })
+    })?;
+
+    let storage_layout;
+    let mut bind_group_layouts = vec![&bind_group_layout];
+    if let Some(stages) = compute_stages {
+        storage_layout = render_storage_layout(device, stages);
+        bind_group_layouts.push(&storage_layout);
+    }
+    let pipeline_layout = with_error_scope(device, "pipeline layout", || {
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pipeline_layout"),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        })
+    })?;
+
+    with_error_scope(device, "render pipeline", || {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("render_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex_shader,
+                entry_point: Some("main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment_shader,
+                entry_point: Some("main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(target_format.into())],
+            }),
+            multiview: None,
+            primitive: wgpu::PrimitiveState {
+                topology,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            cache: None,
+        })
+    })
+}
+
+/// Precision of the offscreen image the user's shader renders into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum HdrFormat {
+    #[default]
+    Rgba16Float,
+    Rgba32Float,
+}
+
+impl HdrFormat {
+    pub const ALL: [Self; 2] = [Self::Rgba16Float, Self::Rgba32Float];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rgba16Float => "RGBA16F",
+            Self::Rgba32Float => "RGBA32F",
+        }
+    }
+
+    pub fn to_wgpu(self) -> wgpu::TextureFormat {
+        match self {
+            Self::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+            Self::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+}
+
+/// Curve mapping the image's unbounded values into the output's 0..1 range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum ToneMapping {
+    /// Cuts values off at 1, as an 8-bit target would.
+    #[default]
+    Clamp,
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+}
+
+impl ToneMapping {
+    pub const ALL: [Self; 3] = [Self::Clamp, Self::Reinhard, Self::Aces];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Clamp => "Clamp",
+            Self::Reinhard => "Reinhard",
+            Self::Aces => "ACES",
+        }
+    }
+}
+
+/// How the offscreen image is stored and turned into the final output.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct OutputSettings {
+    pub format: HdrFormat,
+    pub tone_mapping: ToneMapping,
+    /// In stops; the image is multiplied by `2^exposure` before tone mapping.
+    pub exposure: f32,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            format: HdrFormat::default(),
+            tone_mapping: ToneMapping::default(),
+            exposure: 0.0,
+        }
+    }
+}
+
+/// Matches `Output` in `output.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutputUniform {
+    exposure_scale: f32,
+    tone_mapping: u32,
+    _padding: [u32; 2],
+}
+
+impl OutputUniform {
+    fn new(settings: &OutputSettings) -> Self {
+        Self {
+            exposure_scale: settings.exposure.exp2(),
+            tone_mapping: match settings.tone_mapping {
+                ToneMapping::Clamp => 0,
+                ToneMapping::Reinhard => 1,
+                ToneMapping::Aces => 2,
+            },
+            _padding: [0; 2],
+        }
+    }
+}
+
+fn output_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("output_layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                // Not filterable, so RGBA32F works without extra features.
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+/// The image pass's render target.
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    output_bind_group: wgpu::BindGroup,
+}
+
+pub struct Renderer {
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: Option<wgpu::RenderPipeline>,
+    /// Format `pipeline` was built for; it is not drawn into other targets.
+    pipeline_format: Option<wgpu::TextureFormat>,
+    /// Whether `pipeline` reads the compute storage from bind group 1.
+    pipeline_uses_compute: bool,
+    pub compute: Option<ComputeResources>,
+    /// Dispatched in order before each frame, with their workgroup counts.
+    pub compute_pipelines: Vec<(wgpu::ComputePipeline, [u32; 3])>,
+    output_layout: wgpu::BindGroupLayout,
+    output_pipeline: wgpu::RenderPipeline,
+    output_uniform_buffer: wgpu::Buffer,
+    target: Option<OffscreenTarget>,
+}
+
+impl Renderer {
+    /// `output_format` is the format of the targets [`Self::paint`] draws into.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("uniform_buffer"),
+            size: std::mem::size_of::<WgpuUniform>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group"),
+            layout: &create_bind_group_layout(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let output_layout = output_layout(device);
+        let output_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("output_shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("output.wgsl"))),
+        });
+        let output_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("output_pipeline_layout"),
+                bind_group_layouts: &[&output_layout],
+                push_constant_ranges: &[],
+            });
+        let output_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("output_pipeline"),
+            layout: Some(&output_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &output_shader,
+                entry_point: Some("vs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &output_shader,
+                entry_point: Some("fs_main"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(output_format.into())],
+            }),
+            multiview: None,
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            cache: None,
+        });
+        let output_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output_uniform_buffer"),
+            size: std::mem::size_of::<OutputUniform>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            uniform_buffer,
+            bind_group,
+            pipeline: None,
+            pipeline_format: None,
+            pipeline_uses_compute: false,
+            compute: None,
+            compute_pipelines: Vec::new(),
+            output_layout,
+            output_pipeline,
+            output_uniform_buffer,
+            target: None,
+        }
+    }
+
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    /// Replaces the image pass. `format` is the [`HdrFormat`] it was built
+    /// for and `uses_compute` whether it reads the compute storage.
+    pub fn set_pipeline(
+        &mut self,
+        pipeline: wgpu::RenderPipeline,
+        format: wgpu::TextureFormat,
+        uses_compute: bool,
+    ) {
+        self.pipeline = Some(pipeline);
+        self.pipeline_format = Some(format);
+        self.pipeline_uses_compute = uses_compute;
+    }
+
+    /// The image the last [`Self::prepare`] rendered, before tone mapping.
+    pub fn target(&self) -> Option<&OffscreenTarget> {
+        self.target.as_ref()
+    }
+
+    /// Uploads the uniforms, runs the compute passes and renders the image pass
+    /// into the offscreen target, which is (re)created to match
+    /// `uniform.resolution` and `output.format`.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        uniform: &WgpuUniform,
+        vertex_count: u32,
+        output: &OutputSettings,
+    ) {
+        self.write_uniforms(queue, uniform, output);
+        self.dispatch_compute(encoder);
+        let size = [uniform.resolution.0 as u32, uniform.resolution.1 as u32];
+        self.draw_image(device, encoder, size, vertex_count, output.format);
+    }
+
+    pub fn write_uniforms(
+        &self,
+        queue: &wgpu::Queue,
+        uniform: &WgpuUniform,
+        output: &OutputSettings,
+    ) {
+        queue.write_buffer(&self.uniform_buffer, 0, unsafe {
+            std::slice::from_raw_parts(
+                std::ptr::from_ref::<WgpuUniform>(uniform).cast::<u8>(),
+                std::mem::size_of::<WgpuUniform>(),
+            )
+        });
+        queue.write_buffer(
+            &self.output_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&OutputUniform::new(output)),
+        );
+    }
+
+    /// Advances the compute storage by one frame.
+    pub fn dispatch_compute(&self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(compute) = &self.compute {
+            compute.dispatch(encoder, &self.compute_pipelines);
+        }
+    }
+
+    /// Renders the image pass into a `target_size` offscreen target. This is
+    /// usually the whole image, but may be one tile of a larger one.
+    pub fn draw_image(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        target_size: [u32; 2],
+        vertex_count: u32,
+        format: HdrFormat,
+    ) {
+        let size = wgpu::Extent3d {
+            width: target_size[0].max(1),
+            height: target_size[1].max(1),
+            depth_or_array_layers: 1,
+        };
+        let format = format.to_wgpu();
+        let target_fits = self.target.as_ref().is_some_and(|target| {
+            target.texture.size() == size && target.texture.format() == format
+        });
+        if !target_fits {
+            self.target = Some(self.create_target(device, size, format));
+        }
+        let Some(target) = &self.target else {
+            return;
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("image_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+        if self.pipeline_format != Some(format) {
+            return;
+        }
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        if self.pipeline_uses_compute {
+            match &self.compute {
+                Some(compute) => pass.set_bind_group(1, &compute.render_bind_group, &[]),
+                None => return,
+            }
+        }
+        pass.draw(0..vertex_count, 0..1);
+    }
+
+    fn create_target(
+        &self,
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+    ) -> OffscreenTarget {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let output_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("output_bind_group"),
+            layout: &self.output_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.output_uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        OffscreenTarget {
+            texture,
+            view,
+            output_bind_group,
+        }
+    }
+
+    /// Draws the tone-mapped offscreen target over the pass's viewport.
+    pub fn paint(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        if let Some(target) = &self.target {
+            render_pass.set_pipeline(&self.output_pipeline);
+            render_pass.set_bind_group(0, &target.output_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+#[test]
+fn output_shader_is_valid() {
+    if let Err(err) = crate::app::validate_wgsl(include_str!("output.wgsl"), 0) {
+        panic!("{}", err);
+    }
+}
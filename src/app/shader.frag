@@ -1,5 +1,7 @@
 #version 450
 
+{defines}
+
 layout (binding = 0, std140) uniform Uniforms {{
     vec2 iResolution;
     float iTime;
@@ -10,15 +12,25 @@ layout (binding = 0, std140) uniform Uniforms {{
     vec4 iDate;
     float iSampleRate;
     //vec3 iChannelResolution[4];
+    // Maps gl_FragCoord to fragCoord: scale in xy, offset in zw.
+    vec4 _fragCoordTransform;
+    // Sub-pixel offset of this sample, in pixels, when accumulating
+    // supersampled stills; zero otherwise.
+    vec2 iJitter;
 }};
 
 layout (location = 0) out vec4 _f_color;
 
+{channels}
+
+{compute}
+
+{common}
+
 {content}
 
 void main() {{
-    vec2 fragCoord = gl_FragCoord.xy;
-    fragCoord.y = iResolution.y - fragCoord.y;
+    vec2 fragCoord = gl_FragCoord.xy * _fragCoordTransform.xy + _fragCoordTransform.zw + iJitter;
     mainImage(_f_color, fragCoord);
 }}
 
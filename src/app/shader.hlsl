@@ -0,0 +1,32 @@
+{defines}
+
+cbuffer Uniforms : register(b0) {{
+    float2 iResolution;
+    float iTime;
+    float iTimeDelta;
+    float iFrame;
+    float4 iChannelTime;
+    float4 iMouse;
+    float4 iDate;
+    float iSampleRate;
+    // Maps SV_Position to fragCoord: scale in xy, offset in zw.
+    float4 _fragCoordTransform;
+    // Sub-pixel offset of this sample, in pixels, when accumulating
+    // supersampled stills; zero otherwise.
+    float2 iJitter;
+}};
+
+{channels}
+
+{compute}
+
+{common}
+
+{content}
+
+float4 main(float4 _fragCoord : SV_Position) : SV_Target {{
+    float2 fragCoord = _fragCoord.xy * _fragCoordTransform.xy + _fragCoordTransform.zw + iJitter;
+    float4 fragColor = float4(0.0, 0.0, 0.0, 0.0);
+    mainImage(fragColor, fragCoord);
+    return fragColor;
+}}
@@ -1,58 +1,567 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::app::{parse_compiler_output, SpirvModule};
+use crate::app::{
+    validate_spirv, validate_wgsl, Diagnostic, ShaderCache, TemplateSettings, ValidatedModule,
+};
+use eframe::wgpu;
 use std::borrow::Cow;
 
+/// Language the user writes `mainImage` in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ShaderLanguage {
+    #[default]
+    Glsl,
+    /// Compiled by naga inside wgpu, so it bypasses shaderc and also works on
+    /// the web.
+    Wgsl,
+    /// Compiled by shaderc's HLSL front-end; the uniforms are a `cbuffer`.
+    Hlsl,
+}
+
+impl ShaderLanguage {
+    pub const ALL: [Self; 3] = [Self::Glsl, Self::Wgsl, Self::Hlsl];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Glsl => "GLSL",
+            Self::Wgsl => "WGSL",
+            Self::Hlsl => "HLSL",
+        }
+    }
+
+    /// The shader shown when the app starts or the language is switched.
+    pub fn default_content(self) -> &'static str {
+        match self {
+            Self::Glsl => include_str!("default.glsl"),
+            Self::Wgsl => include_str!("default.wgsl"),
+            Self::Hlsl => include_str!("default.hlsl"),
+        }
+    }
+
+    /// File in `src/app` that wraps the user's `mainImage`.
+    pub fn template_name(self) -> &'static str {
+        match self {
+            Self::Glsl => "shader.frag",
+            Self::Wgsl => "shader.wgsl",
+            Self::Hlsl => "shader.hlsl",
+        }
+    }
+
+    /// Token used for syntax highlighting in the editor.
+    pub fn syntax(self) -> &'static str {
+        match self {
+            Self::Glsl | Self::Hlsl => "c",
+            Self::Wgsl => "rs",
+        }
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Glsl => &["glsl", "frag", "vert"],
+            Self::Wgsl => &["wgsl"],
+            Self::Hlsl => &["hlsl"],
+        }
+    }
+
+    pub fn from_path(path: &std::path::Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|language| language.extensions().contains(&extension))
+    }
+}
+
+/// How hard shaderc's optimizer works on the SPIR-V.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum OptimizationLevel {
+    #[default]
+    None,
+    Size,
+    Performance,
+}
+
+impl OptimizationLevel {
+    pub const ALL: [Self; 3] = [Self::None, Self::Size, Self::Performance];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Size => "Size",
+            Self::Performance => "Performance",
+        }
+    }
+}
+
+/// Vulkan version the SPIR-V targets. Each version also raises the SPIR-V
+/// version to the newest one it guarantees.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize,
+)]
+pub enum TargetEnv {
+    #[default]
+    Vulkan1_0,
+    Vulkan1_1,
+    Vulkan1_2,
+    Vulkan1_3,
+}
+
+impl TargetEnv {
+    pub const ALL: [Self; 4] = [
+        Self::Vulkan1_0,
+        Self::Vulkan1_1,
+        Self::Vulkan1_2,
+        Self::Vulkan1_3,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Vulkan1_0 => "Vulkan 1.0 (SPIR-V 1.0)",
+            Self::Vulkan1_1 => "Vulkan 1.1 (SPIR-V 1.3)",
+            Self::Vulkan1_2 => "Vulkan 1.2 (SPIR-V 1.5)",
+            Self::Vulkan1_3 => "Vulkan 1.3 (SPIR-V 1.6)",
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn versions(self) -> (shaderc::EnvVersion, shaderc::SpirvVersion) {
+        match self {
+            Self::Vulkan1_0 => (shaderc::EnvVersion::Vulkan1_0, shaderc::SpirvVersion::V1_0),
+            Self::Vulkan1_1 => (shaderc::EnvVersion::Vulkan1_1, shaderc::SpirvVersion::V1_3),
+            Self::Vulkan1_2 => (shaderc::EnvVersion::Vulkan1_2, shaderc::SpirvVersion::V1_5),
+            Self::Vulkan1_3 => (shaderc::EnvVersion::Vulkan1_3, shaderc::SpirvVersion::V1_6),
+        }
+    }
+}
+
+/// Compiler options that change the generated SPIR-V, which makes them part of
+/// the cache key. The app saves them for each external shader file, so they
+/// stick to the project between runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CompileSettings {
+    /// Fail the compile on any warning, e.g. when linting in CI.
+    pub warnings_as_errors: bool,
+    pub optimization: OptimizationLevel,
+    pub target_env: TargetEnv,
+    /// Keep names and line information in the SPIR-V, which makes the IR
+    /// viewer easier to read.
+    pub debug_info: bool,
+    pub template: TemplateSettings,
+    /// Whether compute passes are enabled, which declares their storage in
+    /// the image pass. It follows the app state instead of being saved.
+    #[serde(skip)]
+    pub compute: bool,
+}
+
+/// A shader ready to be handed to wgpu.
+pub enum CompiledShader {
+    SpirV(Cow<'static, [u32]>),
+    /// A filled WGSL template, with the number of lines before the content.
+    Wgsl(String, u32),
+}
+
+impl CompiledShader {
+    pub fn validate(&self) -> crate::app::Result<ValidatedModule> {
+        match self {
+            Self::SpirV(spirv) => validate_spirv(spirv),
+            Self::Wgsl(source, content_line) => validate_wgsl(source, *content_line),
+        }
+    }
+
+    /// Size in bytes of what is handed to wgpu.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::SpirV(spirv) => std::mem::size_of_val(spirv.as_ref()),
+            Self::Wgsl(source, _) => source.len(),
+        }
+    }
+
+    pub fn source(&self) -> wgpu::ShaderSource<'_> {
+        match self {
+            Self::SpirV(spirv) => wgpu::ShaderSource::SpirV(Cow::Borrowed(spirv)),
+            Self::Wgsl(source, _) => wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        }
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
-pub fn convert_shader(source: &str, stage: shaderc::ShaderKind) -> crate::app::Result<Vec<u32>> {
-    use anyhow::anyhow;
-    // use shaderc to compile the shader
-    let compiler = shaderc::Compiler::new().ok_or_else(|| anyhow!("Failed to create compiler"))?;
-    // Compile the shader and disable most warnings
+fn compile_options<'a>(
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+) -> crate::app::Result<shaderc::CompileOptions<'a>> {
     let mut compile_options = shaderc::CompileOptions::new()
-        .ok_or_else(|| anyhow!("Failed to create compile options"))?;
-    compile_options.set_suppress_warnings();
-    let binary_result = compiler.compile_into_spirv(
+        .ok_or_else(|| anyhow::anyhow!("Failed to create compile options"))?;
+    if settings.warnings_as_errors {
+        compile_options.set_warnings_as_errors();
+    }
+    compile_options.set_optimization_level(match settings.optimization {
+        OptimizationLevel::None => shaderc::OptimizationLevel::Zero,
+        OptimizationLevel::Size => shaderc::OptimizationLevel::Size,
+        OptimizationLevel::Performance => shaderc::OptimizationLevel::Performance,
+    });
+    let (env_version, spirv_version) = settings.target_env.versions();
+    compile_options.set_target_env(shaderc::TargetEnv::Vulkan, env_version as u32);
+    compile_options.set_target_spirv(spirv_version);
+    if settings.debug_info {
+        compile_options.set_generate_debug_info();
+    }
+    if language == ShaderLanguage::Hlsl {
+        compile_options.set_source_language(shaderc::SourceLanguage::HLSL);
+        // Take bindings from `register(..., spaceN)`, like the compute storage.
+        compile_options.set_hlsl_io_mapping(true);
+    }
+    Ok(compile_options)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn compiler() -> crate::app::Result<shaderc::Compiler> {
+    shaderc::Compiler::new().ok_or_else(|| anyhow::anyhow!("Failed to create compiler"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn convert_shader(
+    source: &str,
+    stage: shaderc::ShaderKind,
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+) -> crate::app::Result<SpirvModule> {
+    // use shaderc to compile the shader
+    let binary_result = compiler()?.compile_into_spirv(
+        source,
+        stage,
+        source_name(language),
+        "main",
+        Some(&compile_options(language, settings)?),
+    )?;
+    Ok(SpirvModule {
+        spirv: binary_result.as_binary().into(),
+        warnings: binary_result.get_warning_messages().into(),
+    })
+}
+
+/// Compiles `source` with the same options as [`convert_shader`], returning the
+/// SPIR-V as assembly text.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn disassemble_shader(
+    source: &str,
+    stage: shaderc::ShaderKind,
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+) -> crate::app::Result<String> {
+    let assembly = compiler()?.compile_into_spirv_assembly(
         source,
         stage,
-        "shader.glsl",
+        source_name(language),
         "main",
-        Some(&compile_options),
+        Some(&compile_options(language, settings)?),
     )?;
-    Ok(binary_result.as_binary().into())
+    Ok(assembly.as_text())
+}
+
+/// File name shaderc reports diagnostics against.
+#[cfg(not(target_arch = "wasm32"))]
+fn source_name(language: ShaderLanguage) -> &'static str {
+    match language {
+        ShaderLanguage::Glsl => "shader.glsl",
+        ShaderLanguage::Wgsl => "shader.wgsl",
+        ShaderLanguage::Hlsl => "shader.hlsl",
+    }
 }
+
+/// Whether built-in templates are read from `src/app` when it exists, so that
+/// editing them takes effect while the app runs from the repository. The
+/// command line always uses the copies compiled into the binary.
+#[cfg(not(target_arch = "wasm32"))]
+static RELOAD_BUILTIN_TEMPLATES: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn reload_builtin_templates(enabled: bool) {
+    RELOAD_BUILTIN_TEMPLATES.store(enabled, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// The built-in template `name`, one of the shader files in `src/app`.
+#[cfg(not(target_arch = "wasm32"))]
+fn builtin_template(name: &str) -> crate::app::Result<String> {
+    let embedded = match name {
+        "shader.frag" => include_str!("shader.frag"),
+        "shader.wgsl" => include_str!("shader.wgsl"),
+        "shader.hlsl" => include_str!("shader.hlsl"),
+        "shader.vert" => include_str!("shader.vert"),
+        "vertex_art.vert" => include_str!("vertex_art.vert"),
+        "vertex_art.frag" => include_str!("vertex_art.frag"),
+        "compute.comp" => include_str!("compute.comp"),
+        _ => return Err(anyhow::anyhow!("There is no built-in template {}", name)),
+    };
+    if RELOAD_BUILTIN_TEMPLATES.load(std::sync::atomic::Ordering::Relaxed) {
+        if let Ok(source) = std::fs::read_to_string(std::path::Path::new("src/app").join(name)) {
+            return Ok(source);
+        }
+    }
+    Ok(embedded.to_owned())
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 macro_rules! load_shader {
-    ($path:literal) => {{
-        let path = format!("src/app/{}", $path);
-        std::fs::read_to_string(&std::path::Path::new(&path))?
-    }};
+    ($name:expr) => {
+        builtin_template($name)?
+    };
 }
 
-pub fn load_vertex_shader() -> crate::app::Result<Cow<'static, [u32]>> {
+/// The project's fragment template for `language`, or the built-in one.
+fn fragment_template(
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+) -> crate::app::Result<String> {
     #[cfg(not(target_arch = "wasm32"))]
     {
-        Ok(convert_shader(&load_shader!("shader.vert"), shaderc::ShaderKind::Vertex)?.into())
+        if let Some(path) = &settings.template.path {
+            return std::fs::read_to_string(path).map_err(|err| {
+                anyhow::anyhow!("Cannot read template {}: {}", path.display(), err)
+            });
+        }
+        Ok(load_shader!(language.template_name()))
     }
     #[cfg(target_arch = "wasm32")]
     {
+        if settings.template.path.is_some() {
+            return Err(anyhow::anyhow!(
+                "Custom templates cannot be read on the web"
+            ));
+        }
+        match language {
+            ShaderLanguage::Wgsl => Ok(include_str!("shader.wgsl").to_owned()),
+            _ => Err(anyhow::anyhow!(
+                "The {} template is not bundled on the web",
+                language.name()
+            )),
+        }
+    }
+}
+
+/// Substitutes `content` into the fragment shader template for `language`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn fragment_shader_source(
+    content: &str,
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+) -> crate::app::Result<String> {
+    let template = fragment_template(language, settings)?;
+    settings
+        .template
+        .fill(&template, content, language, settings.compute)
+}
+
+/// The vertex art template filled in with `content`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn vertex_art_shader_source(
+    content: &str,
+    settings: &CompileSettings,
+) -> crate::app::Result<String> {
+    settings.template.fill(
+        &load_shader!("vertex_art.vert"),
+        content,
+        ShaderLanguage::Glsl,
+        settings.compute,
+    )
+}
+
+/// Compiles `template` filled in with `content`, reusing the
+/// SPIR-V from `cache` when the same inputs were compiled before. Compiler
+/// warnings are appended to `diagnostics`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn convert_shader_cached(
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+    template: &str,
+    content: &str,
+    stage: shaderc::ShaderKind,
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+) -> crate::app::Result<Cow<'static, [u32]>> {
+    let key = crate::app::cache_key(
+        template,
+        content,
+        "",
+        &(format!("{stage:?}"), language, settings),
+    );
+    let module = cache.get_or_compile(key, || {
+        convert_shader(
+            &settings
+                .template
+                .fill(template, content, language, settings.compute)?,
+            stage,
+            language,
+            settings,
+        )
+    })?;
+    diagnostics.extend(parse_compiler_output(&module.warnings));
+    Ok(Cow::Owned(module.spirv.to_vec()))
+}
+
+/// Compiles a GLSL shader that has no placeholders as-is, through `cache`.
+#[cfg(not(target_arch = "wasm32"))]
+fn convert_glsl_cached(
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+    source: &str,
+    stage: shaderc::ShaderKind,
+    settings: &CompileSettings,
+) -> crate::app::Result<Cow<'static, [u32]>> {
+    let language = ShaderLanguage::Glsl;
+    let key = crate::app::cache_key(source, "", "", &(format!("{stage:?}"), language, settings));
+    let module = cache.get_or_compile(key, || convert_shader(source, stage, language, settings))?;
+    diagnostics.extend(parse_compiler_output(&module.warnings));
+    Ok(Cow::Owned(module.spirv.to_vec()))
+}
+
+pub fn load_vertex_shader(
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> crate::app::Result<Cow<'static, [u32]>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let source = load_shader!("shader.vert");
+        convert_glsl_cached(
+            cache,
+            diagnostics,
+            &source,
+            shaderc::ShaderKind::Vertex,
+            settings,
+        )
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (settings, cache, diagnostics);
         let bytes = include_bytes!("shader.vert.spv");
         Ok(Cow::from(bytemuck::cast_slice(bytes)))
     }
 }
-pub fn load_fragment_shader(content: &str) -> crate::app::Result<Cow<'static, [u32]>> {
+pub fn load_fragment_shader(
+    content: &str,
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> crate::app::Result<CompiledShader> {
+    match language {
+        ShaderLanguage::Glsl | ShaderLanguage::Hlsl => {
+            load_spirv_fragment_shader(content, language, settings, cache, diagnostics)
+                .map(CompiledShader::SpirV)
+        }
+        ShaderLanguage::Wgsl => {
+            let template = fragment_template(language, settings)?;
+            let source = settings
+                .template
+                .fill(&template, content, language, settings.compute)?;
+            let content_line =
+                settings
+                    .template
+                    .content_line(&template, language, settings.compute)?;
+            Ok(CompiledShader::Wgsl(source, content_line))
+        }
+    }
+}
+
+/// Compiles the vertex shader the user wrote in the vertex art mode, returning
+/// it with the fragment shader that outputs its `v_color`.
+pub fn load_vertex_art_shaders(
+    content: &str,
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> crate::app::Result<(Cow<'static, [u32]>, CompiledShader)> {
     #[cfg(not(target_arch = "wasm32"))]
     {
-        let template = load_shader!("shader.frag");
-        let map = [("content".to_string(), content.to_string())]
-            .into_iter()
-            .collect::<std::collections::HashMap<String, String>>();
-        convert_shader(
-            &strfmt::strfmt(template.as_str(), &map)?,
+        let vertex = convert_shader_cached(
+            cache,
+            diagnostics,
+            &load_shader!("vertex_art.vert"),
+            content,
+            shaderc::ShaderKind::Vertex,
+            ShaderLanguage::Glsl,
+            settings,
+        )?;
+        let fragment = convert_glsl_cached(
+            cache,
+            diagnostics,
+            &load_shader!("vertex_art.frag"),
+            shaderc::ShaderKind::Fragment,
+            settings,
+        )?;
+        Ok((vertex, CompiledShader::SpirV(fragment)))
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (content, settings, cache, diagnostics);
+        Err(anyhow::anyhow!(
+            "The vertex art mode needs shaderc, which is not available on the web"
+        ))
+    }
+}
+
+/// Compiles one compute pass.
+pub fn load_compute_shader(
+    content: &str,
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> crate::app::Result<Cow<'static, [u32]>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        convert_shader_cached(
+            cache,
+            diagnostics,
+            &load_shader!("compute.comp"),
+            content,
+            shaderc::ShaderKind::Compute,
+            ShaderLanguage::Glsl,
+            settings,
+        )
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = (content, settings, cache, diagnostics);
+        Err(anyhow::anyhow!(
+            "Compute passes need shaderc, which is not available on the web"
+        ))
+    }
+}
+
+/// Compiles GLSL or HLSL through shaderc. On the web, where shaderc is not
+/// available, only the default GLSL shader works, precompiled.
+fn load_spirv_fragment_shader(
+    content: &str,
+    language: ShaderLanguage,
+    settings: &CompileSettings,
+    cache: &mut ShaderCache,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> crate::app::Result<Cow<'static, [u32]>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let template = fragment_template(language, settings)?;
+        convert_shader_cached(
+            cache,
+            diagnostics,
+            &template,
+            content,
             shaderc::ShaderKind::Fragment,
+            language,
+            settings,
         )
-        .map(Cow::from)
     }
     #[cfg(target_arch = "wasm32")]
     {
-        let _ = content;
+        let _ = (settings, cache, diagnostics);
+        if language != ShaderLanguage::Glsl || content != language.default_content() {
+            return Err(anyhow::anyhow!(
+                "Compiling {} needs shaderc, which is not available on the web; use WGSL instead",
+                language.name()
+            ));
+        }
         let bytes = include_bytes!("shader.frag.spv");
         Ok(Cow::from(bytemuck::cast_slice(bytes)))
     }
@@ -70,3 +579,65 @@ fn shader_error() {
     }
 }
 */
+
+/// The web build runs the default GLSL from `shader.frag.spv`, which native
+/// builds regenerate from the template. Catches a template change committed
+/// without the regenerated binary.
+#[test]
+fn precompiled_fragment_shader_matches_the_template() {
+    /// Names and offsets of the uniform block's members. Nested structs are
+    /// flattened, since naga's SPIR-V back-end wraps blocks in one.
+    fn uniform_members(module: &naga::Module) -> Vec<(Option<String>, u32)> {
+        fn flatten(
+            module: &naga::Module,
+            ty: naga::Handle<naga::Type>,
+            base: u32,
+            members: &mut Vec<(Option<String>, u32)>,
+        ) {
+            if let naga::TypeInner::Struct { members: inner, .. } = &module.types[ty].inner {
+                for member in inner {
+                    match module.types[member.ty].inner {
+                        naga::TypeInner::Struct { .. } => {
+                            flatten(module, member.ty, base + member.offset, members)
+                        }
+                        _ => members.push((member.name.clone(), base + member.offset)),
+                    }
+                }
+            }
+        }
+
+        let (_, uniforms) = module
+            .global_variables
+            .iter()
+            .find(|(_, global)| global.space == naga::AddressSpace::Uniform)
+            .expect("no uniform block");
+        let mut members = Vec::new();
+        flatten(module, uniforms.ty, 0, &mut members);
+        members
+    }
+
+    let spirv: Vec<u32> = include_bytes!("shader.frag.spv")
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    let precompiled = validate_spirv(&spirv).unwrap();
+    let source = TemplateSettings::default()
+        .fill(
+            include_str!("shader.frag"),
+            ShaderLanguage::Glsl.default_content(),
+            ShaderLanguage::Glsl,
+            false,
+        )
+        .unwrap();
+    let template = naga::front::glsl::Frontend::default()
+        .parse(
+            &naga::front::glsl::Options::from(naga::ShaderStage::Fragment),
+            &source,
+        )
+        .unwrap_or_else(|err| panic!("{}", err.emit_to_string(&source)));
+    assert_eq!(
+        uniform_members(&precompiled.module),
+        uniform_members(&template),
+        "shader.frag.spv is out of date; rebuild natively to regenerate it"
+    );
+}
@@ -0,0 +1,57 @@
+{defines}
+
+struct Uniforms {{
+    iResolution: vec2<f32>,
+    iTime: f32,
+    iTimeDelta: f32,
+    iFrame: f32,
+    iChannelTime: vec4<f32>,
+    iMouse: vec4<f32>,
+    iDate: vec4<f32>,
+    iSampleRate: f32,
+    // Maps the position builtin to fragCoord: scale in xy, offset in zw.
+    _fragCoordTransform: vec4<f32>,
+    // Sub-pixel offset of this sample, in pixels, when accumulating
+    // supersampled stills; zero otherwise.
+    iJitter: vec2<f32>,
+}}
+
+@group(0) @binding(0) var<uniform> _uniforms: Uniforms;
+
+// Copies of the uniforms so `mainImage` can use the same names as in GLSL.
+var<private> iResolution: vec2<f32>;
+var<private> iTime: f32;
+var<private> iTimeDelta: f32;
+var<private> iFrame: f32;
+var<private> iChannelTime: vec4<f32>;
+var<private> iMouse: vec4<f32>;
+var<private> iDate: vec4<f32>;
+var<private> iSampleRate: f32;
+var<private> iJitter: vec2<f32>;
+
+{channels}
+
+{compute}
+
+{common}
+
+{content}
+
+@fragment
+fn main(@builtin(position) _frag_coord: vec4<f32>) -> @location(0) vec4<f32> {{
+    iResolution = _uniforms.iResolution;
+    iTime = _uniforms.iTime;
+    iTimeDelta = _uniforms.iTimeDelta;
+    iFrame = _uniforms.iFrame;
+    iChannelTime = _uniforms.iChannelTime;
+    iMouse = _uniforms.iMouse;
+    iDate = _uniforms.iDate;
+    iSampleRate = _uniforms.iSampleRate;
+    iJitter = _uniforms.iJitter;
+
+    let fragCoord = _frag_coord.xy * _uniforms._fragCoordTransform.xy
+        + _uniforms._fragCoordTransform.zw + iJitter;
+    var fragColor = vec4<f32>(0.0);
+    mainImage(&fragColor, fragCoord);
+    return fragColor;
+}}
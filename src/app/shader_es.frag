@@ -0,0 +1,31 @@
+#version 310 es
+
+{defines}
+
+precision highp float;
+precision highp int;
+
+layout (binding = 0, std140) uniform Uniforms {{
+    vec2 iResolution;
+    float iTime;
+    float iTimeDelta;
+    float iFrame;
+    vec4 iChannelTime;
+    vec4 iMouse;
+    vec4 iDate;
+    float iSampleRate;
+    vec4 _fragCoordTransform;
+    vec2 iJitter;
+}};
+
+layout (location = 0) out vec4 _f_color;
+
+{channels}
+
+{common}
+
+{content}
+
+void main() {{
+    mainImage(_f_color, gl_FragCoord.xy * _fragCoordTransform.xy + _fragCoordTransform.zw + iJitter);
+}}
@@ -0,0 +1,368 @@
+//! Raw frame streaming for external encoders, so videos can be produced
+//! without linking any codec.
+//!
+//! Three formats are written:
+//!
+//! - `y4m`: YUV4MPEG2 with 4:2:0 BT.709 limited-range frames, which ffmpeg
+//!   and most encoders read without further arguments.
+//! - `rgba`: 8-bit RGBA frames, rows top to bottom.
+//! - `yuv420p`: planar 4:2:0 BT.709 limited-range frames, Y then U then V,
+//!   chroma planes rounded up to whole pixels for odd sizes.
+//!
+//! Unless disabled, `rgba` and `yuv420p` are preceded by a 32-byte header
+//! (all integers little-endian `u32`):
+//!
+//! | offset | field                                   |
+//! |--------|-----------------------------------------|
+//! | 0      | magic `STRSRGBA` or `STRSI420`          |
+//! | 8      | header version, currently 1             |
+//! | 12     | width                                   |
+//! | 16     | height                                  |
+//! | 20     | frame rate numerator                    |
+//! | 24     | frame rate denominator                  |
+//! | 28     | number of frames                        |
+//!
+//! The frames follow back to back, each exactly [`StreamFormat::frame_len`]
+//! bytes.
+
+use crate::app::{Result, RgbaImage};
+use anyhow::{anyhow, bail, Context as _};
+use std::collections::HashMap;
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamFormat {
+    Y4m,
+    Rgba,
+    Yuv420p,
+}
+
+impl StreamFormat {
+    pub const ALL: [Self; 3] = [Self::Y4m, Self::Rgba, Self::Yuv420p];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Y4m => "y4m",
+            Self::Rgba => "rgba",
+            Self::Yuv420p => "yuv420p",
+        }
+    }
+
+    /// ffmpeg's `-pix_fmt` for the frames.
+    pub fn pixel_format(self) -> &'static str {
+        match self {
+            Self::Rgba => "rgba",
+            Self::Y4m | Self::Yuv420p => "yuv420p",
+        }
+    }
+
+    /// Bytes of one frame's pixels, without Y4M's `FRAME` marker.
+    pub fn frame_len(self, size: [u32; 2]) -> usize {
+        let [width, height] = size.map(|side| side as usize);
+        match self {
+            Self::Rgba => width * height * 4,
+            Self::Y4m | Self::Yuv420p => {
+                width * height + 2 * width.div_ceil(2) * height.div_ceil(2)
+            }
+        }
+    }
+}
+
+/// Frame rate as the rational number the containers store. Whole rates are
+/// exact; others are kept to a thousandth of a frame.
+pub fn frame_rate_ratio(fps: f64) -> (u32, u32) {
+    if fps.fract() == 0.0 {
+        return (fps as u32, 1);
+    }
+    let (mut numerator, mut denominator) = ((fps * 1000.0).round() as u32, 1000);
+    let gcd = {
+        let (mut a, mut b) = (numerator, denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a.max(1)
+    };
+    numerator /= gcd;
+    denominator /= gcd;
+    (numerator, denominator)
+}
+
+/// The header in front of `rgba` and `yuv420p` streams.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamHeader {
+    pub format: StreamFormat,
+    pub size: [u32; 2],
+    pub frame_rate: (u32, u32),
+    pub frame_count: u32,
+}
+
+impl StreamHeader {
+    pub const LEN: usize = 32;
+    const VERSION: u32 = 1;
+
+    fn magic(format: StreamFormat) -> Option<&'static [u8; 8]> {
+        match format {
+            StreamFormat::Rgba => Some(b"STRSRGBA"),
+            StreamFormat::Yuv420p => Some(b"STRSI420"),
+            StreamFormat::Y4m => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<[u8; Self::LEN]> {
+        let magic = Self::magic(self.format)
+            .ok_or_else(|| anyhow!("Y4M streams carry their own header"))?;
+        let mut bytes = [0; Self::LEN];
+        bytes[..8].copy_from_slice(magic);
+        let fields = [
+            Self::VERSION,
+            self.size[0],
+            self.size[1],
+            self.frame_rate.0,
+            self.frame_rate.1,
+            self.frame_count,
+        ];
+        for (chunk, field) in bytes[8..].chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&field.to_le_bytes());
+        }
+        Ok(bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let bytes = bytes
+            .get(..Self::LEN)
+            .ok_or_else(|| anyhow!("The stream header is {} bytes", Self::LEN))?;
+        let format = StreamFormat::ALL
+            .into_iter()
+            .find(|&format| Self::magic(format).is_some_and(|magic| bytes[..8] == magic[..]))
+            .ok_or_else(|| anyhow!("Not a shadertoy_rs frame stream"))?;
+        let field = |index: usize| {
+            let offset = 8 + index * 4;
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        if field(0) != Self::VERSION {
+            bail!("Unsupported stream header version {}", field(0));
+        }
+        Ok(Self {
+            format,
+            size: [field(1), field(2)],
+            frame_rate: (field(3), field(4)),
+            frame_count: field(5),
+        })
+    }
+}
+
+/// Writes frames in a [`StreamFormat`], header first.
+pub struct FrameStream<W: Write> {
+    writer: W,
+    format: StreamFormat,
+    size: [u32; 2],
+}
+
+impl<W: Write> FrameStream<W> {
+    /// `header` only applies to `rgba` and `yuv420p`; Y4M always has one.
+    pub fn new(
+        mut writer: W,
+        format: StreamFormat,
+        size: [u32; 2],
+        fps: f64,
+        frame_count: u32,
+        header: bool,
+    ) -> Result<Self> {
+        let frame_rate = frame_rate_ratio(fps);
+        match format {
+            StreamFormat::Y4m => writeln!(
+                writer,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg XYSCSS=420JPEG XCOLORRANGE=LIMITED",
+                size[0], size[1], frame_rate.0, frame_rate.1
+            )?,
+            _ if header => writer.write_all(
+                &StreamHeader {
+                    format,
+                    size,
+                    frame_rate,
+                    frame_count,
+                }
+                .to_bytes()?,
+            )?,
+            _ => {}
+        }
+        Ok(Self {
+            writer,
+            format,
+            size,
+        })
+    }
+
+    pub fn write_frame(&mut self, image: &RgbaImage) -> Result<()> {
+        if [image.width, image.height] != self.size {
+            bail!(
+                "Frame is {}x{}, the stream {}x{}",
+                image.width,
+                image.height,
+                self.size[0],
+                self.size[1]
+            );
+        }
+        match self.format {
+            StreamFormat::Rgba => self.writer.write_all(&image.pixels)?,
+            StreamFormat::Yuv420p => self.writer.write_all(&rgba_to_yuv420p(image))?,
+            StreamFormat::Y4m => {
+                self.writer.write_all(b"FRAME\n")?;
+                self.writer.write_all(&rgba_to_yuv420p(image))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Converts to planar 4:2:0 with BT.709 coefficients in limited range. The
+/// frame's values are already gamma encoded, so they are used as is; chroma
+/// is taken from the average of each 2x2 block.
+pub fn rgba_to_yuv420p(image: &RgbaImage) -> Vec<u8> {
+    const KR: f32 = 0.2126;
+    const KB: f32 = 0.0722;
+    let luma = |rgb: [f32; 3]| KR * rgb[0] + (1.0 - KR - KB) * rgb[1] + KB * rgb[2];
+    let to_byte = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+
+    let [width, height] = [image.width as usize, image.height as usize];
+    let rgb = |x: usize, y: usize| {
+        let offset = (y * width + x) * 4;
+        let pixel = &image.pixels[offset..offset + 3];
+        [0, 1, 2].map(|channel| f32::from(pixel[channel]) / 255.0)
+    };
+    let mut planes =
+        Vec::with_capacity(StreamFormat::Yuv420p.frame_len([image.width, image.height]));
+    for y in 0..height {
+        for x in 0..width {
+            planes.push(to_byte(16.0 + 219.0 * luma(rgb(x, y))));
+        }
+    }
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut u = Vec::with_capacity(chroma_width * chroma_height);
+    let mut v = Vec::with_capacity(chroma_width * chroma_height);
+    for y in 0..chroma_height {
+        for x in 0..chroma_width {
+            let mut sum = [0.0f32; 3];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                // Odd sizes repeat the last row or column.
+                let pixel = rgb((2 * x + dx).min(width - 1), (2 * y + dy).min(height - 1));
+                for channel in 0..3 {
+                    sum[channel] += pixel[channel] / 4.0;
+                }
+            }
+            let y = luma(sum);
+            u.push(to_byte(128.0 + 224.0 * (sum[2] - y) / (2.0 * (1.0 - KB))));
+            v.push(to_byte(128.0 + 224.0 * (sum[0] - y) / (2.0 * (1.0 - KR))));
+        }
+    }
+    planes.extend_from_slice(&u);
+    planes.extend_from_slice(&v);
+    planes
+}
+
+/// Starts `command` through the shell with its stdin piped, after replacing
+/// `{width}`, `{height}`, `{fps}` and `{pix_fmt}`, e.g.
+/// `ffmpeg -y -f rawvideo -pix_fmt {pix_fmt} -s {width}x{height} -r {fps} -i - out.mp4`.
+pub fn spawn_encoder(
+    command: &str,
+    format: StreamFormat,
+    size: [u32; 2],
+    fps: f64,
+) -> Result<std::process::Child> {
+    let (numerator, denominator) = frame_rate_ratio(fps);
+    let variables = HashMap::from([
+        ("width".to_owned(), size[0].to_string()),
+        ("height".to_owned(), size[1].to_string()),
+        ("fps".to_owned(), format!("{}/{}", numerator, denominator)),
+        ("pix_fmt".to_owned(), format.pixel_format().to_owned()),
+    ]);
+    let command = strfmt::strfmt(command, &variables)
+        .with_context(|| format!("Invalid encoder command `{}`", command))?;
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell
+        .arg(&command)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start `{}`", command))
+}
+
+#[test]
+fn stream_header_round_trip() {
+    let header = StreamHeader {
+        format: StreamFormat::Yuv420p,
+        size: [1920, 1080],
+        frame_rate: frame_rate_ratio(29.97),
+        frame_count: 300,
+    };
+    assert_eq!(header.frame_rate, (2997, 100));
+    let bytes = header.to_bytes().unwrap();
+    assert_eq!(&bytes[..8], b"STRSI420");
+    assert_eq!(StreamHeader::parse(&bytes).unwrap(), header);
+}
+
+#[test]
+fn yuv_planes() {
+    let image = RgbaImage {
+        width: 3,
+        height: 1,
+        pixels: [[255, 255, 255, 255], [0, 0, 0, 255], [255, 0, 0, 255]].concat(),
+    };
+    let planes = rgba_to_yuv420p(&image);
+    assert_eq!(planes.len(), StreamFormat::Yuv420p.frame_len([3, 1]));
+    assert_eq!(&planes[..3], &[235, 16, 63]);
+    // White and black average to grey, which has no chroma.
+    assert_eq!(&planes[3..], &[128, 102, 128, 240]);
+}
+
+#[cfg(unix)]
+#[test]
+fn encoder_receives_every_byte() {
+    let dir = std::env::temp_dir().join(format!("shadertoy_rs_encoder_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (format, size, frames) = (StreamFormat::Yuv420p, [6, 4], 3);
+    let mut child = spawn_encoder(
+        &format!(
+            "cd '{}' && echo {{width}}x{{height}} {{fps}} {{pix_fmt}} > info && wc -c > count",
+            dir.display()
+        ),
+        format,
+        size,
+        30.0,
+    )
+    .unwrap();
+    let stdin = child.stdin.take().unwrap();
+    let mut stream = FrameStream::new(stdin, format, size, 30.0, frames, true).unwrap();
+    let image = RgbaImage {
+        width: size[0],
+        height: size[1],
+        pixels: vec![128; 6 * 4 * 4],
+    };
+    for _ in 0..frames {
+        stream.write_frame(&image).unwrap();
+    }
+    // Closing stdin ends the encoder's input.
+    drop(stream.finish().unwrap());
+    assert!(child.wait().unwrap().success());
+
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+    assert_eq!(read("info").trim(), "6x4 30/1 yuv420p");
+    let count: usize = read("count").trim().parse().unwrap();
+    assert_eq!(
+        count,
+        StreamHeader::LEN + frames as usize * format.frame_len(size)
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
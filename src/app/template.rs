@@ -0,0 +1,133 @@
+//! Fragment shader templates wrap the user's `mainImage` into a complete
+//! shader. A project can replace the built-in template for its language with
+//! its own file, e.g. to declare shared uniforms or change how the output is
+//! written. Templates use `strfmt` syntax, so literal braces are doubled
+//! (`{{` and `}}`), and may use these placeholders:
+//!
+//! - `{content}`: the shader being edited. In GLSL and HLSL it is preceded by
+//!   `#line 1`, so compiler messages count lines from the start of the shader.
+//! - `{common}`: code shared by every shader of the project, inserted before
+//!   `{content}` in the built-in templates.
+//! - `{defines}`: the project's defines, as `#define` lines for GLSL and HLSL
+//!   and as `const` declarations for WGSL. In GLSL it must come after
+//!   `#version`.
+//! - `{channels}`: declarations for the `iChannel` inputs. Channels are not
+//!   bound yet, so this expands to nothing for now.
+//! - `{compute}`: read access to the compute pass storage (`iData` and
+//!   `iComputeTexture`) while compute passes are enabled, nothing otherwise.
+
+use crate::app::{compute_declarations, ShaderLanguage};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct TemplateSettings {
+    /// Template used instead of the built-in one. It has to be written in the
+    /// language of the shader being edited.
+    pub path: Option<PathBuf>,
+    /// Substituted for `{common}`.
+    pub common: String,
+    /// One `NAME` or `NAME=VALUE` per line, substituted for `{defines}`.
+    pub defines: String,
+}
+
+impl TemplateSettings {
+    /// Substitutes `content` and the project settings into `template`.
+    /// `compute` says whether compute passes are enabled.
+    pub fn fill(
+        &self,
+        template: &str,
+        content: &str,
+        language: ShaderLanguage,
+        compute: bool,
+    ) -> crate::app::Result<String> {
+        let compute = match compute {
+            true => compute_declarations(language),
+            false => "",
+        };
+        let content = match language {
+            ShaderLanguage::Glsl | ShaderLanguage::Hlsl => format!("#line 1\n{}", content),
+            // WGSL has no `#line`; see `content_line`.
+            ShaderLanguage::Wgsl => content.to_owned(),
+        };
+        let map: HashMap<String, String> = [
+            ("content", content),
+            ("common", self.common.clone()),
+            ("defines", self.defines_source(language)),
+            ("channels", String::new()),
+            ("compute", compute.to_owned()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value))
+        .collect();
+        Ok(strfmt::strfmt(template, &map)?)
+    }
+
+    /// Number of lines before `{content}` once `template` is filled, which is
+    /// subtracted from the lines naga reports for WGSL.
+    pub fn content_line(
+        &self,
+        template: &str,
+        language: ShaderLanguage,
+        compute: bool,
+    ) -> crate::app::Result<u32> {
+        let source = self.fill(template, "\0", language, compute)?;
+        let prefix = &source[..source.find('\0').unwrap_or(0)];
+        Ok(prefix.matches('\n').count() as u32)
+    }
+
+    fn defines_source(&self, language: ShaderLanguage) -> String {
+        let mut source = String::new();
+        for line in self
+            .defines
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+        {
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (line, None),
+            };
+            match (language, value) {
+                (ShaderLanguage::Glsl | ShaderLanguage::Hlsl, Some(value)) => {
+                    source.push_str(&format!("#define {} {}\n", name, value));
+                }
+                (ShaderLanguage::Glsl | ShaderLanguage::Hlsl, None) => {
+                    source.push_str(&format!("#define {}\n", name));
+                }
+                // WGSL has no preprocessor, so defines become constants.
+                (ShaderLanguage::Wgsl, value) => {
+                    source.push_str(&format!("const {} = {};\n", name, value.unwrap_or("true")));
+                }
+            }
+        }
+        source
+    }
+}
+
+#[test]
+fn fill_template_placeholders() {
+    let settings = TemplateSettings {
+        path: None,
+        common: "float common();".to_owned(),
+        defines: "FAST\n STEPS = 64 \n".to_owned(),
+    };
+    let template = "{defines}{channels}{common}\n{content}\nvoid main() {{}}";
+    assert_eq!(
+        settings
+            .fill(template, "void mainImage();", ShaderLanguage::Glsl, false)
+            .unwrap(),
+        "#define FAST\n#define STEPS 64\nfloat common();\n#line 1\nvoid mainImage();\nvoid main() {}"
+    );
+    assert_eq!(
+        settings
+            .content_line(template, ShaderLanguage::Wgsl, false)
+            .unwrap(),
+        3
+    );
+    assert_eq!(
+        settings.defines_source(ShaderLanguage::Wgsl),
+        "const FAST = true;\nconst STEPS = 64;\n"
+    );
+}
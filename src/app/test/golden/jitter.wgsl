@@ -0,0 +1,7 @@
+// Stripes that change if pixel centers move off +0.5 or iJitter is not zero
+// in a plain render
+fn mainImage(fragColor: ptr<function, vec4<f32>>, fragCoord: vec2<f32>) {
+    let stripes = fract(fragCoord / 2.0);
+    let jitter = 4.0 * (abs(iJitter.x) + abs(iJitter.y));
+    *fragColor = vec4<f32>(stripes, 0.5 + jitter, 1.0);
+}
@@ -0,0 +1,11 @@
+// A square moving right from the bottom-left corner over a gradient, which
+// breaks if fragCoord is flipped or offset
+fn mainImage(fragColor: ptr<function, vec4<f32>>, fragCoord: vec2<f32>) {
+    let uv = fragCoord / iResolution.xy;
+    var color = vec3<f32>(uv, 0.25);
+    let square = fragCoord - vec2<f32>(64.0 * iTime, 0.0);
+    if (all(square >= vec2<f32>(0.0)) && all(square < vec2<f32>(32.0))) {
+        color = vec3<f32>(1.0);
+    }
+    *fragColor = vec4<f32>(color, 1.0);
+}
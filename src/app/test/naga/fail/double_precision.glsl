@@ -0,0 +1,6 @@
+// Double precision needs the FLOAT64 capability, which wgpu only enables with
+// the SHADER_F64 feature
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    double t = double(iTime) * 0.5lf;
+    fragColor = vec4(vec3(float(fract(t))), 1.0);
+}
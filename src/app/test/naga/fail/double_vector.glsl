@@ -0,0 +1,5 @@
+// Double precision vectors are rejected for the same reason as scalars
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    dvec2 uv = dvec2(fragCoord) / dvec2(iResolution.xy);
+    fragColor = vec4(vec2(uv), 0.0, 1.0);
+}
@@ -0,0 +1,22 @@
+// Constant arrays, integer switch and the mouse uniform
+const vec3 palette[4] = vec3[4](
+    vec3(0.9, 0.2, 0.2),
+    vec3(0.2, 0.9, 0.2),
+    vec3(0.2, 0.2, 0.9),
+    vec3(0.9, 0.9, 0.2)
+);
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    int cell = int(fragCoord.x / 32.0 + fragCoord.y / 32.0) & 3;
+    vec3 color;
+    switch (cell) {
+        case 0: color = palette[0]; break;
+        case 1: color = palette[1]; break;
+        case 2: color = palette[2]; break;
+        default: color = palette[3]; break;
+    }
+    if (distance(fragCoord, iMouse.xy) < 10.0) {
+        color = vec3(1.0);
+    }
+    fragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,11 @@
+// A simple color gradient shader
+void mainImage(out float4 fragColor, in float2 fragCoord) {
+    // Normalized coordinates [0, 1]
+    float2 uv = fragCoord / iResolution.xy;
+
+    // Create gradient color
+    float3 color = float3(uv.x, uv.y, sin(iTime) * 0.5 + 0.5);
+
+    // Output color
+    fragColor = float4(color, 1.0);
+}
@@ -0,0 +1,11 @@
+// A simple color gradient shader
+fn mainImage(fragColor: ptr<function, vec4<f32>>, fragCoord: vec2<f32>) {
+    // Normalized coordinates [0, 1]
+    let uv = fragCoord / iResolution.xy;
+
+    // Create gradient color
+    let color = vec3<f32>(uv.x, uv.y, sin(iTime) * 0.5 + 0.5);
+
+    // Output color
+    *fragColor = vec4<f32>(color, 1.0);
+}
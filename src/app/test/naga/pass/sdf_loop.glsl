@@ -0,0 +1,18 @@
+// Sphere tracing with a fixed iteration count and an early exit
+float map(vec3 p) {
+    return length(p - vec3(0.0, 0.0, 3.0)) - 1.0;
+}
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = (2.0 * fragCoord - iResolution.xy) / iResolution.y;
+    vec3 rd = normalize(vec3(uv, 1.5));
+    float t = 0.0;
+    for (int i = 0; i < 64; i++) {
+        float d = map(rd * t);
+        if (d < 0.001) {
+            break;
+        }
+        t += d;
+    }
+    fragColor = vec4(vec3(exp(-0.3 * t)), 1.0);
+}
@@ -0,0 +1,133 @@
+use crate::app::SourceError;
+use anyhow::anyhow;
+use naga::front::spv;
+
+/// SPIR-V parsed and validated by naga, the front-end wgpu uses internally.
+pub struct ValidatedModule {
+    pub module: naga::Module,
+    pub info: naga::valid::ModuleInfo,
+}
+
+/// Parses `spirv` with the same options wgpu uses and runs naga's validator on
+/// it, so SPIR-V that shaderc accepts but wgpu would reject is reported before
+/// a pipeline is created.
+pub fn validate_spirv(spirv: &[u32]) -> crate::app::Result<ValidatedModule> {
+    let options = spv::Options {
+        adjust_coordinate_space: false,
+        strict_capabilities: true,
+        block_ctx_dump_prefix: None,
+    };
+    let module = spv::Frontend::new(spirv.iter().cloned(), &options)
+        .parse()
+        .map_err(|err| match parse_hint(&err) {
+            Some(hint) => anyhow!("naga cannot read the SPIR-V: {}\nhint: {}", err, hint),
+            None => anyhow!("naga cannot read the SPIR-V: {}", err),
+        })?;
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    )
+    .validate(&module)
+    .map_err(|err| anyhow!("naga rejected the SPIR-V: {}", error_chain(&err)))?;
+    Ok(ValidatedModule { module, info })
+}
+
+/// Parses and validates WGSL with naga. Errors with a location are
+/// [`SourceError`]s, with `content_line` lines subtracted so they count from
+/// the start of the shader being edited rather than its template.
+pub fn validate_wgsl(source: &str, content_line: u32) -> crate::app::Result<ValidatedModule> {
+    let locate = |location: Option<naga::SourceLocation>, message: String| match location {
+        Some(location) => anyhow::Error::new(SourceError {
+            line: match location.line_number > content_line {
+                true => location.line_number - content_line,
+                false => location.line_number,
+            },
+            column: location.line_position,
+            message,
+        }),
+        None => anyhow!("{}", message),
+    };
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|err| locate(err.location(source), err.message().to_owned()))?;
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::default(),
+    )
+    .validate(&module)
+    .map_err(|err| locate(err.location(source), error_chain(&err)))?;
+    Ok(ValidatedModule { module, info })
+}
+
+/// Suggests what to change in the GLSL for the SPIR-V features naga is known
+/// not to support.
+fn parse_hint(err: &spv::Error) -> Option<&'static str> {
+    match err {
+        spv::Error::UnsupportedCapability(_) | spv::Error::UnknownCapability(_) => Some(
+            "the shader uses a feature wgpu cannot translate (e.g. subgroup operations or \
+             sparse textures); remove the extension that enables it",
+        ),
+        spv::Error::UnsupportedExtension(_) | spv::Error::UnsupportedExtSet(_) => {
+            Some("only the GLSL.std.450 instruction set is supported; remove #extension lines")
+        }
+        spv::Error::UnsupportedExtInstSet(_) | spv::Error::UnsupportedExtInst(_) => {
+            Some("a built-in function has no naga equivalent; replace it with plain arithmetic")
+        }
+        spv::Error::UnsupportedControlFlow(_) => {
+            Some("simplify the control flow, e.g. avoid `return` inside `switch` or loops")
+        }
+        spv::Error::UnsupportedInstruction(..) | spv::Error::UnsupportedBinaryOperator(_) => {
+            Some("an operation has no naga equivalent; try rewriting the expression")
+        }
+        spv::Error::UnsupportedImageFormat(_) | spv::Error::UnsupportedImageDim(_) => {
+            Some("use a 2D/3D/cube image with a standard format")
+        }
+        _ => None,
+    }
+}
+
+/// Joins an error with its sources; naga's validation errors nest the
+/// interesting part (the missing capability, the offending expression) deep in
+/// the chain.
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn naga_corpus() {
+    use crate::app::{load_fragment_shader, CompileSettings, ShaderCache, ShaderLanguage};
+
+    for (dir, should_pass) in [
+        ("src/app/test/naga/pass", true),
+        ("src/app/test/naga/fail", false),
+    ] {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let content = std::fs::read_to_string(&path).unwrap();
+            let language = ShaderLanguage::from_path(&path).unwrap();
+            let shader = load_fragment_shader(
+                &content,
+                language,
+                &CompileSettings::default(),
+                &mut ShaderCache::in_memory(),
+                &mut Vec::new(),
+            )
+            .unwrap_or_else(|err| panic!("{} does not compile: {}", path.display(), err));
+            let result = shader.validate();
+            assert_eq!(
+                result.is_ok(),
+                should_pass,
+                "{}: {:?}",
+                path.display(),
+                result.err()
+            );
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use eframe::wgpu;
+
+/// What the user's shader draws.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum RenderMode {
+    /// `mainImage` runs for every pixel of a fullscreen quad, as on Shadertoy.
+    #[default]
+    Fragment,
+    /// The user writes the vertex shader and picks how many vertices are
+    /// drawn, in the style of vertexshaderart.com.
+    VertexArt,
+}
+
+impl RenderMode {
+    pub const ALL: [Self; 2] = [Self::Fragment, Self::VertexArt];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fragment => "Fragment",
+            Self::VertexArt => "Vertex art",
+        }
+    }
+
+    /// Stages of the image pass that read the compute storage. Only the
+    /// vertex art shader reads it from the vertex stage, which not every
+    /// adapter allows.
+    pub fn compute_stages(self) -> wgpu::ShaderStages {
+        match self {
+            Self::Fragment => wgpu::ShaderStages::FRAGMENT,
+            Self::VertexArt => wgpu::ShaderStages::VERTEX_FRAGMENT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Topology {
+    #[default]
+    Points,
+    Lines,
+    LineStrip,
+    Triangles,
+    TriangleStrip,
+}
+
+impl Topology {
+    pub const ALL: [Self; 5] = [
+        Self::Points,
+        Self::Lines,
+        Self::LineStrip,
+        Self::Triangles,
+        Self::TriangleStrip,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Points => "Points",
+            Self::Lines => "Lines",
+            Self::LineStrip => "Line strip",
+            Self::Triangles => "Triangles",
+            Self::TriangleStrip => "Triangle strip",
+        }
+    }
+
+    pub fn to_wgpu(self) -> wgpu::PrimitiveTopology {
+        match self {
+            Self::Points => wgpu::PrimitiveTopology::PointList,
+            Self::Lines => wgpu::PrimitiveTopology::LineList,
+            Self::LineStrip => wgpu::PrimitiveTopology::LineStrip,
+            Self::Triangles => wgpu::PrimitiveTopology::TriangleList,
+            Self::TriangleStrip => wgpu::PrimitiveTopology::TriangleStrip,
+        }
+    }
+}
+
+/// Settings of the vertex art mode. `content` is a GLSL vertex shader body
+/// with a `main()` that writes `gl_Position` and `v_color`, and can read
+/// `vertexId` and `vertexCount`. The vertex count and topology are saved with
+/// the app state; the shader is not, like the fragment shader.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct VertexArt {
+    #[serde(skip)]
+    pub content: String,
+    pub vertex_count: u32,
+    pub topology: Topology,
+}
+
+impl Default for VertexArt {
+    fn default() -> Self {
+        Self {
+            content: include_str!("default_vertex_art.glsl").to_owned(),
+            vertex_count: 10_000,
+            topology: Topology::default(),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+#version 450
+
+{defines}
+
+layout (binding = 0, std140) uniform Uniforms {{
+    vec2 iResolution;
+    float iTime;
+    float iTimeDelta;
+    float iFrame;
+    vec4 iChannelTime;
+    vec4 iMouse;
+    vec4 iDate;
+    float iSampleRate;
+    vec4 _fragCoordTransform;
+    vec2 iJitter;
+    float vertexCount;
+}};
+
+layout (location = 0) out vec4 v_color;
+
+#define vertexId float(gl_VertexIndex)
+
+{channels}
+
+{compute}
+
+{common}
+
+{content}
@@ -0,0 +1,778 @@
+//! Command line interface. Without a subcommand the app opens its window.
+
+use crate::app::{
+    build_gallery, check_file, frame_uniform, gallery_html, run_golden, spawn_encoder,
+    AnimationFormat, AnimationOptions, AnimationWriter, CheckResult, CompileSettings, ComputePass,
+    ComputeSettings, Diagnostic, Dither, ExportPass, FloatFormat, FrameClock, FrameStream,
+    GalleryOptions, GalleryStatus, GifOptions, GifPalette, GoldenOptions, GoldenTolerance,
+    HdrFormat, HeadlessRenderer, LoopCheck, OutputSettings, Result, Severity, ShaderCache,
+    ShaderLanguage, StreamFormat,
+};
+use anyhow::{anyhow, bail, Context as _};
+use log::{debug, info, warn};
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+#[derive(clap::Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Render one frame of a shader to a PNG, OpenEXR or Radiance HDR image
+    /// without opening a window.
+    Render(RenderArgs),
+    /// Render a range of frames at a fixed frame rate to numbered PNGs.
+    Export(ExportArgs),
+    /// Render a range of frames into an animated GIF, APNG or WebP.
+    Animate(AnimateArgs),
+    /// Write a range of frames as raw video to stdout, a file or named pipe,
+    /// or an encoder command.
+    Stream(StreamArgs),
+    /// Render one frame in tiles, for images larger than the GPU's texture
+    /// limit, streaming the PNG to disk.
+    Poster(PosterArgs),
+    /// Compile and validate shaders without a GPU, e.g. to lint them in CI.
+    /// Exits with an error if any file fails.
+    Check(CheckArgs),
+    /// Render a directory of shaders at fixed times and compare them with
+    /// their reference images in its `golden` directory.
+    Golden(GoldenArgs),
+    /// Render a thumbnail of every shader in a directory tree into a static
+    /// HTML gallery.
+    Gallery(GalleryArgs),
+}
+
+impl Command {
+    pub fn run(self) -> Result<()> {
+        match self {
+            Self::Render(args) => render(args),
+            Self::Export(args) => export(args),
+            Self::Animate(args) => animate(args),
+            Self::Stream(args) => stream(args),
+            Self::Poster(args) => poster(args),
+            Self::Check(args) => check(args),
+            Self::Golden(args) => golden(args),
+            Self::Gallery(args) => gallery(args),
+        }
+    }
+}
+
+/// What to render and where, shared by the rendering subcommands.
+#[derive(clap::Args)]
+pub struct ShaderArgs {
+    /// Shader to render; the language is taken from the file extension.
+    shader: PathBuf,
+    /// GLSL compute pass to run before each frame. Repeat to run several in
+    /// order; their storage persists between frames.
+    #[arg(long = "compute", value_name = "FILE")]
+    compute_passes: Vec<PathBuf>,
+    /// Image size as WIDTHxHEIGHT.
+    #[arg(long, default_value = "800x450", value_parser = parse_size)]
+    size: [u32; 2],
+    /// Use a software adapter (llvmpipe, lavapipe, WARP) even when a GPU is
+    /// available.
+    #[arg(long)]
+    software: bool,
+}
+
+#[derive(clap::Args)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    shader: ShaderArgs,
+    /// Value of iTime, in seconds.
+    #[arg(long, default_value_t = 0.0)]
+    time: f32,
+    /// Value of iFrame. Defaults to the frame `--time` falls on at 60 fps.
+    #[arg(long)]
+    frame: Option<u32>,
+    /// Render N x N samples per pixel, offset within the pixel through
+    /// iJitter.
+    #[arg(long, default_value_t = 1, value_name = "N")]
+    supersample: u32,
+    /// Accumulate this many jittered passes of the supersampling grid.
+    #[arg(long, default_value_t = 1, value_name = "FRAMES")]
+    accumulate: u32,
+    /// Image to export to .exr or .hdr files: image (the image pass before
+    /// tone mapping, in RGBA32F), output (tone mapped) or compute
+    /// (iComputeImage).
+    #[arg(long, default_value = "image", value_parser = parse_export_pass)]
+    pass: ExportPass,
+    /// File to write. .exr and .hdr files keep the unclamped float values;
+    /// anything else is written as a PNG of the tone-mapped output.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+/// Which frames to render, shared by the sequence subcommands.
+#[derive(clap::Args)]
+pub struct FrameRangeArgs {
+    /// First frame to write.
+    #[arg(long, default_value_t = 0)]
+    first: u32,
+    /// Last frame to write, inclusive.
+    #[arg(
+        long,
+        required_unless_present = "duration",
+        conflicts_with = "duration"
+    )]
+    last: Option<u32>,
+    /// Length in seconds instead of `--last`; for loops, the time at which the
+    /// animation wraps back to `--first`.
+    #[arg(long)]
+    duration: Option<f64>,
+    /// Frames per second; frame N is rendered at iTime = N / fps.
+    #[arg(long, default_value_t = 60.0)]
+    fps: f64,
+}
+
+impl FrameRangeArgs {
+    fn frames(&self) -> Result<(FrameClock, RangeInclusive<u32>)> {
+        if self.fps.is_nan() || self.fps <= 0.0 {
+            bail!("--fps must be positive");
+        }
+        let last = match (self.last, self.duration) {
+            (Some(last), _) => last,
+            (None, Some(duration)) => {
+                let count = (duration * self.fps).round();
+                if count.is_nan() || count < 1.0 {
+                    bail!("--duration must cover at least one frame");
+                }
+                self.first.saturating_add(count as u32 - 1)
+            }
+            (None, None) => bail!("Either --last or --duration is required"),
+        };
+        if last < self.first {
+            bail!("--last ({}) is before --first ({})", last, self.first);
+        }
+        Ok((FrameClock::new(self.fps), self.first..=last))
+    }
+}
+
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    shader: ShaderArgs,
+    #[command(flatten)]
+    frames: FrameRangeArgs,
+    /// Path of each frame, with `%d` or e.g. `%04d` replaced by the frame
+    /// number, as in `frames/frame_%04d.png`.
+    #[arg(short, long)]
+    output: String,
+}
+
+#[derive(clap::Args)]
+pub struct AnimateArgs {
+    #[command(flatten)]
+    shader: ShaderArgs,
+    #[command(flatten)]
+    frames: FrameRangeArgs,
+    /// How many times the animation plays; 0 loops forever.
+    #[arg(long, default_value_t = 0)]
+    plays: u16,
+    /// GIF palette size.
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u16).range(2..=256))]
+    colors: u16,
+    /// GIF dithering: none or floyd-steinberg.
+    #[arg(long, default_value = "floyd-steinberg", value_parser = parse_dither)]
+    dither: Dither,
+    /// GIF palette: global (trained on the first frame) or per-frame.
+    #[arg(long, default_value = "global", value_parser = parse_gif_palette)]
+    palette: GifPalette,
+    /// GIF, APNG or WebP file to write, chosen by the extension (.gif,
+    /// .png/.apng, .webp).
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+fn parse_size(size: &str) -> std::result::Result<[u32; 2], String> {
+    let parse = |value: &str| match value.trim().parse::<u32>() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(format!("invalid size `{}`, expected e.g. 1920x1080", size)),
+    };
+    let (width, height) = size
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("invalid size `{}`, expected e.g. 1920x1080", size))?;
+    Ok([parse(width)?, parse(height)?])
+}
+
+#[derive(clap::Args)]
+pub struct StreamArgs {
+    #[command(flatten)]
+    shader: ShaderArgs,
+    #[command(flatten)]
+    frames: FrameRangeArgs,
+    /// Frame format: y4m (YUV4MPEG2), rgba or yuv420p. The raw formats are
+    /// preceded by a 32-byte header unless `--no-header` is given.
+    #[arg(long, default_value = "y4m", value_parser = parse_stream_format)]
+    format: StreamFormat,
+    /// Leave out the header of the rgba and yuv420p formats.
+    #[arg(long)]
+    no_header: bool,
+    /// File or named pipe to write to, or `-` for stdout.
+    #[arg(short, long, default_value = "-", conflicts_with = "encoder")]
+    output: PathBuf,
+    /// Shell command to feed the frames to on stdin. `{width}`, `{height}`,
+    /// `{fps}` and `{pix_fmt}` are replaced, e.g. `ffmpeg -y -i - out.mp4`
+    /// for y4m or `ffmpeg -y -f rawvideo -pix_fmt {pix_fmt} -s
+    /// {width}x{height} -r {fps} -i - out.mp4` with `--no-header`.
+    #[arg(long)]
+    encoder: Option<String>,
+}
+
+#[derive(clap::Args)]
+pub struct PosterArgs {
+    #[command(flatten)]
+    shader: ShaderArgs,
+    /// Value of iTime, in seconds.
+    #[arg(long, default_value_t = 0.0)]
+    time: f32,
+    /// Value of iFrame. Defaults to the frame `--time` falls on at 60 fps.
+    #[arg(long)]
+    frame: Option<u32>,
+    /// Largest tile rendered at once, in pixels; capped to the GPU's texture
+    /// limit. One row of tiles is kept in memory.
+    #[arg(long, default_value_t = 2048)]
+    tile: u32,
+    /// PNG file to write.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args)]
+pub struct CheckArgs {
+    /// Shaders to check. Image shaders are compiled in the template for
+    /// their extension's language, `.comp` files as GLSL compute passes.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// Report format: human or json.
+    #[arg(long, default_value = "human", value_parser = parse_report_format)]
+    format: ReportFormat,
+    /// Declare the compute storage (iData, iComputeTexture) in image shaders,
+    /// as when compute passes are enabled.
+    #[arg(long)]
+    with_compute: bool,
+    /// Fail on warnings too.
+    #[arg(long)]
+    warnings_as_errors: bool,
+}
+
+#[derive(clap::Args)]
+pub struct GoldenArgs {
+    /// Directory of shaders; references are read from and written to its
+    /// `golden` subdirectory as `<file name>@<time>.png`.
+    dir: PathBuf,
+    /// Image size as WIDTHxHEIGHT.
+    #[arg(long, default_value = "256x144", value_parser = parse_size)]
+    size: [u32; 2],
+    /// Value of iTime to render every shader at. Repeat for several.
+    #[arg(long = "time", value_name = "SECONDS", default_values_t = [0.0, 1.0])]
+    times: Vec<f32>,
+    /// Per-pixel perceptual difference, 0 to 1, above which a pixel counts as
+    /// changed.
+    #[arg(long, default_value_t = 0.1)]
+    threshold: f32,
+    /// Percentage of changed pixels allowed before a case fails.
+    #[arg(long, default_value_t = 0.1, value_name = "PERCENT")]
+    max_mismatch: f64,
+    /// Directory for the rendered images and diffs of failing cases.
+    #[arg(long, default_value = "golden-diff")]
+    diff_dir: PathBuf,
+    /// Replace the references with the rendered images.
+    #[arg(long)]
+    update: bool,
+    /// Fail on missing references instead of creating them.
+    #[arg(long, conflicts_with = "update")]
+    require_references: bool,
+    /// Render on a hardware adapter. References are best kept on the
+    /// software one, which renders the same on every machine.
+    #[arg(long)]
+    hardware: bool,
+}
+
+#[derive(clap::Args)]
+pub struct GalleryArgs {
+    /// Directory searched for shaders, including its subdirectories.
+    dir: PathBuf,
+    /// Thumbnail size as WIDTHxHEIGHT.
+    #[arg(long, default_value = "320x180", value_parser = parse_size)]
+    size: [u32; 2],
+    /// Value of iTime the thumbnails are rendered at.
+    #[arg(long, default_value_t = 1.0)]
+    time: f32,
+    /// Page title. Defaults to the directory's name.
+    #[arg(long)]
+    title: Option<String>,
+    /// Use a software adapter even when a GPU is available.
+    #[arg(long)]
+    software: bool,
+    /// Directory to write index.html and the thumbnails to.
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Human,
+    Json,
+}
+
+impl ReportFormat {
+    pub const ALL: [Self; 2] = [Self::Human, Self::Json];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Human => "human",
+            Self::Json => "json",
+        }
+    }
+}
+
+fn parse_report_format(name: &str) -> std::result::Result<ReportFormat, String> {
+    ReportFormat::ALL
+        .into_iter()
+        .find(|format| format.name() == name)
+        .ok_or_else(|| format!("expected human or json, got `{}`", name))
+}
+
+fn parse_stream_format(name: &str) -> std::result::Result<StreamFormat, String> {
+    StreamFormat::ALL
+        .into_iter()
+        .find(|format| format.name() == name)
+        .ok_or_else(|| format!("expected y4m, rgba or yuv420p, got `{}`", name))
+}
+
+fn parse_export_pass(name: &str) -> std::result::Result<ExportPass, String> {
+    ExportPass::ALL
+        .into_iter()
+        .find(|pass| pass.name() == name)
+        .ok_or_else(|| format!("expected image, output or compute, got `{}`", name))
+}
+
+fn parse_dither(name: &str) -> std::result::Result<Dither, String> {
+    Dither::ALL
+        .into_iter()
+        .find(|dither| dither.name() == name)
+        .ok_or_else(|| format!("expected none or floyd-steinberg, got `{}`", name))
+}
+
+fn parse_gif_palette(name: &str) -> std::result::Result<GifPalette, String> {
+    GifPalette::ALL
+        .into_iter()
+        .find(|palette| palette.name() == name)
+        .ok_or_else(|| format!("expected global or per-frame, got `{}`", name))
+}
+
+/// Reads and compiles the shaders into a new headless renderer, printing
+/// compiler warnings to stderr.
+fn load_shaders(args: &ShaderArgs, output: OutputSettings) -> Result<HeadlessRenderer> {
+    let path = &args.shader;
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let language = ShaderLanguage::from_path(path)
+        .ok_or_else(|| anyhow!("Unknown shader language for {}", path.display()))?;
+    let mut compute = ComputeSettings::default();
+    for path in &args.compute_passes {
+        compute.passes.push(ComputePass {
+            content: std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+            ..ComputePass::default()
+        });
+    }
+
+    let mut renderer = HeadlessRenderer::new(args.software)?;
+    let adapter = renderer.adapter_info();
+    info!("Rendering on {} ({:?})", adapter.name, adapter.backend);
+
+    let mut diagnostics = Vec::new();
+    let result = renderer.set_shaders(
+        &content,
+        language,
+        &CompileSettings::default(),
+        &compute,
+        output,
+        &mut diagnostics,
+    );
+    if let Err(err) = &result {
+        diagnostics.extend(Diagnostic::from_error(err));
+    }
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    result.with_context(|| format!("{} does not compile", path.display()))?;
+    Ok(renderer)
+}
+
+fn render(args: RenderArgs) -> Result<()> {
+    let float_format = FloatFormat::from_path(&args.output);
+    let pass = match float_format {
+        Some(_) => args.pass,
+        None => ExportPass::Output,
+    };
+    if float_format == Some(FloatFormat::Hdr) {
+        warn!("Radiance HDR has no alpha channel; use .exr to keep it");
+    }
+    if pass == ExportPass::ComputeImage && args.shader.compute_passes.is_empty() {
+        bail!("--pass compute needs at least one --compute pass");
+    }
+    // Float exports render the image pass in full single precision.
+    let mut output = OutputSettings::default();
+    if float_format.is_some() {
+        output.format = HdrFormat::Rgba32Float;
+    }
+    let mut renderer = load_shaders(&args.shader, output)?;
+    let clock = FrameClock::default();
+    let frame = args.frame.unwrap_or_else(|| clock.frame_at(args.time));
+    let size = args.shader.size;
+    renderer.warm_up(size, &clock, frame)?;
+    let uniform = frame_uniform(size, args.time, clock.time_delta(), frame);
+    let image = if args.supersample > 1 || args.accumulate > 1 {
+        renderer.render_accumulated(
+            &uniform,
+            pass,
+            args.supersample,
+            args.accumulate,
+            |done, total| {
+                eprint!("\rSample {}/{}", done, total);
+                if done == total {
+                    eprintln!();
+                }
+            },
+        )?
+    } else {
+        renderer.render_float(&uniform, pass)?
+    };
+    match float_format {
+        Some(_) => image.write_float(&args.output)?,
+        None => image.to_rgba8().write_png(&args.output)?,
+    }
+    info!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+/// Substitutes `frame` for the `%d` / `%0Nd` placeholder in `pattern`.
+fn frame_path(pattern: &str, frame: u32) -> Result<PathBuf> {
+    let start = pattern
+        .find('%')
+        .ok_or_else(|| anyhow!("`{}` has no %d placeholder for the frame number", pattern))?;
+    let rest = &pattern[start + 1..];
+    let end = rest
+        .find('d')
+        .filter(|&end| rest[..end].bytes().all(|byte| byte.is_ascii_digit()))
+        .ok_or_else(|| anyhow!("`{}` has no %d placeholder for the frame number", pattern))?;
+    let width = rest[..end].parse::<usize>().unwrap_or(0);
+    Ok(PathBuf::from(format!(
+        "{}{:0width$}{}",
+        &pattern[..start],
+        frame,
+        &rest[end + 1..],
+        width = width
+    )))
+}
+
+fn export(args: ExportArgs) -> Result<()> {
+    let (clock, frames) = args.frames.frames()?;
+    // Fail on a bad pattern before rendering anything.
+    frame_path(&args.output, *frames.start())?;
+
+    let mut renderer = load_shaders(&args.shader, OutputSettings::default())?;
+    renderer.render_frames(args.shader.size, &clock, frames.clone(), |frame, image| {
+        let path = frame_path(&args.output, frame)?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        image.write_png(&path)?;
+        debug!("Wrote {}", path.display());
+        Ok(())
+    })?;
+    info!(
+        "Wrote frames {} to {} at {} fps",
+        frames.start(),
+        frames.end(),
+        clock.fps
+    );
+    Ok(())
+}
+
+fn animate(args: AnimateArgs) -> Result<()> {
+    let (clock, frames) = args.frames.frames()?;
+    let options = AnimationOptions {
+        fps: clock.fps,
+        plays: args.plays,
+        gif: GifOptions {
+            colors: usize::from(args.colors),
+            dither: args.dither,
+            palette: args.palette,
+            ..GifOptions::default()
+        },
+    };
+    if AnimationFormat::from_path(&args.output) == Some(AnimationFormat::Gif) && clock.fps > 50.0 {
+        warn!("GIF frame delays are in 1/100 s and many viewers slow down delays under 2/100 s; use at most 50 fps");
+    }
+
+    let mut renderer = load_shaders(&args.shader, OutputSettings::default())?;
+    let frame_count = frames.end() - frames.start() + 1;
+    let mut writer =
+        AnimationWriter::create(&args.output, args.shader.size, frame_count, &options)?;
+    let mut loop_check = LoopCheck::default();
+    renderer.render_frames(args.shader.size, &clock, frames, |frame, image| {
+        debug!("Encoding frame {}", frame);
+        loop_check.add_frame(&image);
+        writer.write_frame(&image)
+    })?;
+    writer.finish()?;
+    info!("Wrote {}", args.output.display());
+
+    if args.plays != 1 {
+        if let Some(report) = loop_check.report() {
+            if report.is_seamless() {
+                info!(
+                    "The loop is seamless: the last frame differs from the first by {:.2}%, frames from each other by {:.2}% on average",
+                    report.seam * 100.0,
+                    report.mean_step * 100.0
+                );
+            } else {
+                warn!(
+                    "The loop jumps: the last frame differs from the first by {:.2}%, frames from each other by {:.2}% on average; try another --duration",
+                    report.seam * 100.0,
+                    report.mean_step * 100.0
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn stream(args: StreamArgs) -> Result<()> {
+    let (clock, frames) = args.frames.frames()?;
+    let size = args.shader.size;
+    let frame_count = frames.end() - frames.start() + 1;
+    let mut renderer = load_shaders(&args.shader, OutputSettings::default())?;
+
+    let mut encoder = None;
+    let writer: Box<dyn Write> = if let Some(command) = &args.encoder {
+        let mut child = spawn_encoder(command, args.format, size, clock.fps)?;
+        let stdin = child.stdin.take().context("The encoder has no stdin")?;
+        encoder = Some(child);
+        Box::new(stdin)
+    } else if args.output.as_os_str() == "-" {
+        Box::new(std::io::stdout().lock())
+    } else {
+        // Opening a named pipe blocks until the reader connects.
+        let file = std::fs::File::create(&args.output)
+            .with_context(|| format!("Failed to open {}", args.output.display()))?;
+        Box::new(file)
+    };
+    let mut stream = FrameStream::new(
+        std::io::BufWriter::new(writer),
+        args.format,
+        size,
+        clock.fps,
+        frame_count,
+        !args.no_header,
+    )?;
+    let result = renderer
+        .render_frames(size, &clock, frames, |frame, image| {
+            debug!("Streaming frame {}", frame);
+            stream.write_frame(&image)
+        })
+        .and_then(|()| stream.finish().map(drop));
+
+    // The stream is closed by now, so the encoder sees the end of its input.
+    if let Some(mut child) = encoder {
+        let status = child.wait().context("Failed to wait for the encoder")?;
+        if !status.success() {
+            bail!("The encoder exited with {}", status);
+        }
+    }
+    result?;
+    info!("Streamed {} frames", frame_count);
+    Ok(())
+}
+
+fn poster(args: PosterArgs) -> Result<()> {
+    let mut renderer = load_shaders(&args.shader, OutputSettings::default())?;
+    let clock = FrameClock::default();
+    let frame = args.frame.unwrap_or_else(|| clock.frame_at(args.time));
+    let [width, height] = args.shader.size;
+    renderer.warm_up(args.shader.size, &clock, frame)?;
+
+    let file = std::fs::File::create(&args.output)
+        .with_context(|| format!("Failed to create {}", args.output.display()))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?.into_stream_writer()?;
+    let mut rows_written = 0;
+    renderer.render_tiled(
+        args.shader.size,
+        args.tile,
+        args.time,
+        clock.time_delta(),
+        frame,
+        |rows| {
+            writer.write_all(rows)?;
+            rows_written += rows.len() / (width as usize * 4);
+            debug!("Wrote {} of {} rows", rows_written, height);
+            Ok(())
+        },
+    )?;
+    writer.finish()?;
+    info!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+fn check(args: CheckArgs) -> Result<()> {
+    let settings = CompileSettings {
+        warnings_as_errors: args.warnings_as_errors,
+        compute: args.with_compute,
+        ..CompileSettings::default()
+    };
+    let mut cache = ShaderCache::in_memory();
+    let results: Vec<CheckResult> = args
+        .files
+        .iter()
+        .map(|path| check_file(path, &settings, &mut cache))
+        .collect();
+    let count = |severity| {
+        results
+            .iter()
+            .map(|result| result.count(severity))
+            .sum::<usize>()
+    };
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+    let failed = results.iter().filter(|result| !result.passed()).count();
+
+    match args.format {
+        ReportFormat::Human => {
+            for result in &results {
+                for diagnostic in &result.diagnostics {
+                    println!("{}: {}", result.path.display(), diagnostic);
+                }
+            }
+            println!(
+                "Checked {} files: {} failed, {} errors, {} warnings",
+                results.len(),
+                failed,
+                errors,
+                warnings
+            );
+        }
+        ReportFormat::Json => {
+            let report = serde_json::json!({
+                "files": results,
+                "failed": failed,
+                "errors": errors,
+                "warnings": warnings,
+            });
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &report)?;
+            writeln!(stdout)?;
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} files failed to compile", failed, results.len());
+    }
+    Ok(())
+}
+
+fn golden(args: GoldenArgs) -> Result<()> {
+    let options = GoldenOptions {
+        size: args.size,
+        times: args.times,
+        tolerance: GoldenTolerance {
+            threshold: args.threshold,
+            max_mismatch: args.max_mismatch / 100.0,
+        },
+        diff_dir: args.diff_dir,
+        update: args.update,
+        create_missing: !args.require_references,
+    };
+    let mut renderer = HeadlessRenderer::new(!args.hardware)?;
+    let adapter = renderer.adapter_info();
+    info!("Rendering on {} ({:?})", adapter.name, adapter.backend);
+    let results = run_golden(&mut renderer, &args.dir, &options)?;
+    for result in &results {
+        println!("{}", result);
+    }
+    let failed = results
+        .iter()
+        .filter(|result| result.status.is_failure())
+        .count();
+    println!("{} cases, {} failed", results.len(), failed);
+    if failed > 0 {
+        bail!("{} of {} golden images do not match", failed, results.len());
+    }
+    Ok(())
+}
+
+fn gallery(args: GalleryArgs) -> Result<()> {
+    let options = GalleryOptions {
+        size: args.size,
+        time: args.time,
+    };
+    let mut renderer = HeadlessRenderer::new(args.software)?;
+    let adapter = renderer.adapter_info();
+    info!("Rendering on {} ({:?})", adapter.name, adapter.backend);
+    let entries =
+        build_gallery(
+            &mut renderer,
+            &args.dir,
+            &args.output,
+            &options,
+            |entry| match &entry.status {
+                GalleryStatus::Rendered { .. } => debug!("Rendered {}", entry.path.display()),
+                GalleryStatus::Failed(_) => warn!("{} does not compile", entry.path.display()),
+            },
+        )?;
+    let title = args.title.unwrap_or_else(|| {
+        std::fs::canonicalize(&args.dir)
+            .ok()
+            .and_then(|dir| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "Shaders".to_owned())
+    });
+    let index = args.output.join("index.html");
+    std::fs::write(&index, gallery_html(&title, &entries, &options))
+        .with_context(|| format!("Failed to write {}", index.display()))?;
+    let failed = entries
+        .iter()
+        .filter(|entry| matches!(entry.status, GalleryStatus::Failed(_)))
+        .count();
+    info!(
+        "Wrote {} with {} shaders, {} failing",
+        index.display(),
+        entries.len(),
+        failed
+    );
+    Ok(())
+}
+
+#[test]
+fn parse_sizes() {
+    assert_eq!(parse_size("1920x1080"), Ok([1920, 1080]));
+    assert_eq!(parse_size("64X32"), Ok([64, 32]));
+    assert!(parse_size("1920").is_err());
+    assert!(parse_size("0x10").is_err());
+}
+
+#[test]
+fn frame_paths() {
+    assert_eq!(
+        frame_path("frames/frame_%04d.png", 7).unwrap(),
+        PathBuf::from("frames/frame_0007.png")
+    );
+    assert_eq!(
+        frame_path("%d.png", 12345).unwrap(),
+        PathBuf::from("12345.png")
+    );
+    assert!(frame_path("frame.png", 0).is_err());
+    assert!(frame_path("frame_%s.png", 0).is_err());
+}
@@ -1,4 +1,6 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
 pub use app::*;
@@ -3,8 +3,17 @@ use shadertoy_rs::App;
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result {
+    use clap::Parser as _;
     use eframe::egui;
-    use std::sync::Arc;
+
+    if let Some(command) = shadertoy_rs::cli::Cli::parse().command {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+        if let Err(err) = command.run() {
+            eprintln!("error: {:#}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -20,6 +29,8 @@ fn main() -> eframe::Result {
             // Add simplified Chinese font support
             #[cfg(target_os = "windows")]
             {
+                use std::sync::Arc;
+
                 // Try to load system font
                 if let Ok(font_data) = std::fs::read("C:\\Windows\\Fonts\\msyh.ttc") {
                     let mut fonts = egui::FontDefinitions::default();